@@ -0,0 +1,371 @@
+use crate::data::secret::Secret;
+use crate::data::storage::{AzureCredential, AzureStorageOptions, StorageOptions};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::env;
+use std::fmt;
+
+
+// settings for one workspace, every value is optional until the layers are merged
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ProfileConfig {
+    pub workspace_name: Option<String>,
+    pub db_token: Option<Secret>,
+    pub database_url: Option<String>,
+    pub migrations_path: Option<String>,
+    pub principal: Option<String>,
+    pub storage: Option<StorageOptions>,
+    #[serde(skip)]
+    pub azure_env: Option<AzureEnvCredential>, // only set by the environment layer
+}
+impl ProfileConfig {
+    // values set in `other` win
+    fn merge(self, other: ProfileConfig) -> ProfileConfig {
+        ProfileConfig {
+            workspace_name: other.workspace_name.or(self.workspace_name),
+            db_token: other.db_token.or(self.db_token),
+            database_url: other.database_url.or(self.database_url),
+            migrations_path: other.migrations_path.or(self.migrations_path),
+            principal: other.principal.or(self.principal),
+            storage: match (self.storage, other.storage) {
+                (Some(base), Some(over)) => Some(StorageOptions {
+                    azure: over.azure.or(base.azure),
+                    s3: over.s3.or(base.s3),
+                    gcs: over.gcs.or(base.gcs),
+                }),
+                (base, over) => over.or(base),
+            },
+            azure_env: other.azure_env.or(self.azure_env),
+        }
+    }
+}
+
+// azure client secret credentials from the environment, the values stay optional so validation can report the missing ones
+#[derive(Debug, Clone, Default)]
+pub struct AzureEnvCredential {
+    pub account_name: String,
+    pub client_id: Option<String>,
+    pub client_secret: Option<Secret>,
+    pub tenant_id: Option<String>,
+}
+
+// the toml config file, one table per named profile
+//
+// default_profile = "dev"
+//
+// [profiles.dev]
+// workspace_name = "adb-984752964297111.11.azuredatabricks.net"
+// database_url = "sqlite://catalog.db"
+// migrations_path = "src/sql/migrations"
+//
+// [profiles.dev.storage.azure]
+// account_name = "mystorageaccount"
+// credential = { type = "client_secret", client_id = "...", client_secret = "...", tenant_id = "..." }
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ConfigFile {
+    pub default_profile: Option<String>,
+    #[serde(default)]
+    pub profiles: HashMap<String, ProfileConfig>,
+}
+
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(String, std::io::Error),
+    Parse(String, toml::de::Error),
+    UnknownProfile(String),
+    InvalidArgument(String),
+    Missing(Vec<String>),
+}
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io(path, e) => write!(f, "Unable to read config file {}: {}", path, e),
+            ConfigError::Parse(path, e) => write!(f, "Unable to parse config file {}: {}", path, e),
+            ConfigError::UnknownProfile(name) => write!(f, "Profile '{}' is not defined in the config file", name),
+            ConfigError::InvalidArgument(arg) => write!(f, "Invalid argument: {}", arg),
+            ConfigError::Missing(values) => write!(f, "Missing configuration values:\n  {}", values.join("\n  ")),
+        }
+    }
+}
+impl std::error::Error for ConfigError {}
+
+
+// validated configuration for the active profile
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub profile: String,
+    pub workspace_name: String,
+    pub db_token: Secret,
+    pub database_url: String,
+    pub migrations_path: String,
+    pub principal: String,
+    pub storage: StorageOptions,
+}
+impl Config {
+    /// Loads configuration from the config file, environment variables and command line arguments, later layers win.
+    /// The file defaults to `config.toml` and can be set with `--config` or `CONFIG_PATH`, the profile with `--profile` or `DATABRICKS_PROFILE`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let config: Config = Config::load()?;
+    /// ```
+    pub fn load() -> Result<Config, ConfigError> {
+        let args: Vec<String> = env::args().skip(1).collect();
+        Config::load_from(&args)
+    }
+
+    pub fn load_from(args: &[String]) -> Result<Config, ConfigError> {
+        let cli: HashMap<String, String> = parse_args(args)?;
+
+        // the file is optional unless it was asked for explicitly
+        let explicit_path: Option<String> = cli.get("config").cloned().or(env::var("CONFIG_PATH").ok());
+        let path: String = explicit_path.clone().unwrap_or(String::from("config.toml"));
+        let file: ConfigFile = match std::fs::read_to_string(&path) {
+            Ok(contents) => toml::from_str(&contents).map_err(|e| ConfigError::Parse(path.clone(), e))?,
+            Err(e) if explicit_path.is_some() => return Err(ConfigError::Io(path, e)),
+            Err(_) => ConfigFile::default(),
+        };
+
+        let profile: String = cli.get("profile").cloned()
+            .or(env::var("DATABRICKS_PROFILE").ok())
+            .or(file.default_profile.clone())
+            .unwrap_or(String::from("default"));
+        let file_profile: ProfileConfig = match file.profiles.get(&profile) {
+            Some(p) => p.clone(),
+            None if file.profiles.is_empty() || profile == "default" => ProfileConfig::default(),
+            None => return Err(ConfigError::UnknownProfile(profile)),
+        };
+
+        let merged: ProfileConfig = file_profile.merge(env_layer()).merge(cli_layer(&cli));
+        Config::validate(profile, merged)
+    }
+
+    // reports every missing value at once
+    fn validate(profile: String, merged: ProfileConfig) -> Result<Config, ConfigError> {
+        let mut missing: Vec<String> = Vec::new();
+        let mut require = |value: &Option<String>, name: &str, env_var: &str, flag: &str| {
+            if value.as_deref().is_none_or(|v| v.is_empty()) {
+                missing.push(format!("{} (set `{}` in the config file, {} or --{})", name, name, env_var, flag));
+            }
+        };
+        require(&merged.workspace_name, "workspace_name", "WORKSPACE_NAME", "workspace-name");
+        require(&merged.database_url, "database_url", "DATABASE_URL", "database-url");
+        require(&merged.migrations_path, "migrations_path", "MIGRATIONS_PATH", "migrations-path");
+        require(&merged.principal, "principal", "PRINCIPAL", "principal");
+        if merged.db_token.as_ref().is_none_or(|t| t.expose_secret().is_empty()) {
+            missing.push(String::from("db_token (set `db_token` in the config file or DB_TOKEN)"));
+        }
+        // an azure account from the environment needs all of the client secret values
+        let mut storage: StorageOptions = merged.storage.unwrap_or_default();
+        if let Some(azure) = merged.azure_env {
+            match (azure.client_id, azure.client_secret, azure.tenant_id) {
+                (Some(client_id), Some(client_secret), Some(tenant_id)) => {
+                    storage.azure = Some(AzureStorageOptions {
+                        account_name: azure.account_name,
+                        credential: AzureCredential::ClientSecret { client_id, client_secret, tenant_id },
+                    });
+                }
+                (client_id, client_secret, tenant_id) => {
+                    let values: [(&str, &str, bool); 3] = [("client_id", "AZURE_CLIENT_ID", client_id.is_none()), ("client_secret", "AZURE_CLIENT_SECRET", client_secret.is_none()), ("tenant_id", "AZURE_TENANT_ID", tenant_id.is_none())];
+                    for (name, env_var, _) in values.iter().filter(|(_, _, unset)| *unset) {
+                        missing.push(format!("azure {} (set {} when AZURE_STORAGE_ACCOUNT_NAME is set)", name, env_var));
+                    }
+                }
+            }
+        }
+        if !missing.is_empty() {
+            return Err(ConfigError::Missing(missing));
+        }
+
+        Ok(Config {
+            profile,
+            workspace_name: merged.workspace_name.unwrap(),
+            db_token: merged.db_token.unwrap(),
+            database_url: merged.database_url.unwrap(),
+            migrations_path: merged.migrations_path.unwrap(),
+            principal: merged.principal.unwrap(),
+            storage,
+        })
+    }
+}
+
+// --key value or --key=value pairs
+fn parse_args(args: &[String]) -> Result<HashMap<String, String>, ConfigError> {
+    let mut values: HashMap<String, String> = HashMap::new();
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        let key: &str = arg.strip_prefix("--").ok_or_else(|| ConfigError::InvalidArgument(arg.clone()))?;
+        match key.split_once('=') {
+            Some((k, v)) => {
+                values.insert(k.to_string(), v.to_string());
+            }
+            None => {
+                let value: &String = iter.next().ok_or_else(|| ConfigError::InvalidArgument(format!("--{} requires a value", key)))?;
+                values.insert(key.to_string(), value.clone());
+            }
+        }
+    }
+    Ok(values)
+}
+
+fn env_layer() -> ProfileConfig {
+    let var = |name: &str| env::var(name).ok().filter(|v| !v.is_empty());
+
+    // azure client secret credentials are only used when the account is set
+    let azure_env: Option<AzureEnvCredential> = var("AZURE_STORAGE_ACCOUNT_NAME").map(|account_name| AzureEnvCredential {
+        account_name,
+        client_id: var("AZURE_CLIENT_ID"),
+        client_secret: var("AZURE_CLIENT_SECRET").map(Secret::from),
+        tenant_id: var("AZURE_TENANT_ID"),
+    });
+
+    ProfileConfig {
+        workspace_name: var("WORKSPACE_NAME"),
+        db_token: var("DB_TOKEN").map(Secret::from),
+        database_url: var("DATABASE_URL"),
+        migrations_path: var("MIGRATIONS_PATH"),
+        principal: var("PRINCIPAL"),
+        storage: None,
+        azure_env,
+    }
+}
+
+// tokens are never taken from the command line as they would end up in shell history
+fn cli_layer(cli: &HashMap<String, String>) -> ProfileConfig {
+    ProfileConfig {
+        workspace_name: cli.get("workspace-name").cloned(),
+        db_token: None,
+        database_url: cli.get("database-url").cloned(),
+        migrations_path: cli.get("migrations-path").cloned(),
+        principal: cli.get("principal").cloned(),
+        storage: None,
+        azure_env: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use tempfile::NamedTempFile;
+
+    const CONFIG: &str = r#"
+default_profile = "dev"
+
+[profiles.dev]
+workspace_name = "dev.cloud.databricks.com"
+db_token = "dev-token"
+database_url = "sqlite://dev.db"
+migrations_path = "src/sql/migrations"
+principal = "dev@example.com"
+
+[profiles.dev.storage.s3]
+region = "us-east-1"
+
+[profiles.prod]
+workspace_name = "prod.cloud.databricks.com"
+"#;
+
+    fn args(values: &[&str]) -> Vec<String> {
+        values.iter().map(|v| v.to_string()).collect()
+    }
+
+    #[test]
+    fn later_layers_win() {
+        let file: ConfigFile = toml::from_str(CONFIG).unwrap();
+        let env: ProfileConfig = ProfileConfig { principal: Some(String::from("env@example.com")), ..Default::default() };
+        let cli: ProfileConfig = cli_layer(&parse_args(&args(&["--principal", "cli@example.com", "--database-url=sqlite://cli.db"])).unwrap());
+        let merged: ProfileConfig = file.profiles["dev"].clone().merge(env).merge(cli);
+        assert_eq!(merged.workspace_name.as_deref(), Some("dev.cloud.databricks.com"));
+        assert_eq!(merged.principal.as_deref(), Some("cli@example.com"));
+        assert_eq!(merged.database_url.as_deref(), Some("sqlite://cli.db"));
+        assert_eq!(merged.db_token.map(|t| t.expose_secret().to_string()), Some(String::from("dev-token")));
+    }
+
+    #[test]
+    fn storage_options_merge_per_cloud() {
+        let file: ConfigFile = toml::from_str(CONFIG).unwrap();
+        let env: ProfileConfig = ProfileConfig {
+            storage: Some(StorageOptions::new().with_azure(AzureStorageOptions {
+                account_name: String::from("account"),
+                credential: AzureCredential::SasToken { sas_token: Secret::from("sas") },
+            })),
+            ..Default::default()
+        };
+        let storage: StorageOptions = file.profiles["dev"].clone().merge(env).storage.unwrap();
+        assert!(storage.azure.is_some());
+        assert_eq!(storage.s3.and_then(|s3| s3.region).as_deref(), Some("us-east-1"));
+    }
+
+    #[test]
+    fn reports_every_missing_value() {
+        let partial: ProfileConfig = ProfileConfig { workspace_name: Some(String::from("dev.cloud.databricks.com")), ..Default::default() };
+        match Config::validate(String::from("dev"), partial) {
+            Err(ConfigError::Missing(missing)) => {
+                assert_eq!(missing.len(), 4);
+                assert!(missing.iter().any(|m| m.starts_with("db_token")));
+                assert!(!missing.iter().any(|m| m.starts_with("workspace_name")));
+            }
+            other => panic!("expected missing values, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn reports_missing_azure_client_secret_values() {
+        let partial: ProfileConfig = ProfileConfig {
+            workspace_name: Some(String::from("dev.cloud.databricks.com")),
+            db_token: Some(Secret::from("token")),
+            database_url: Some(String::from("sqlite://dev.db")),
+            migrations_path: Some(String::from("src/sql/migrations")),
+            principal: Some(String::from("dev@example.com")),
+            azure_env: Some(AzureEnvCredential {
+                account_name: String::from("account"),
+                client_id: Some(String::from("client")),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        match Config::validate(String::from("dev"), partial.clone()) {
+            Err(ConfigError::Missing(missing)) => {
+                assert_eq!(missing.len(), 2);
+                assert!(missing.iter().any(|m| m.contains("AZURE_CLIENT_SECRET")));
+                assert!(missing.iter().any(|m| m.contains("AZURE_TENANT_ID")));
+            }
+            other => panic!("expected missing values, got {:?}", other),
+        }
+
+        let complete: ProfileConfig = ProfileConfig {
+            azure_env: Some(AzureEnvCredential {
+                account_name: String::from("account"),
+                client_id: Some(String::from("client")),
+                client_secret: Some(Secret::from("secret")),
+                tenant_id: Some(String::from("tenant")),
+            }),
+            ..partial
+        };
+        let config: Config = Config::validate(String::from("dev"), complete).unwrap();
+        assert_eq!(config.storage.azure.map(|a| a.account_name).as_deref(), Some("account"));
+    }
+
+    #[test]
+    fn parses_both_argument_forms() {
+        let values: HashMap<String, String> = parse_args(&args(&["--profile", "prod", "--config=dev.toml"])).unwrap();
+        assert_eq!(values.get("profile").map(String::as_str), Some("prod"));
+        assert_eq!(values.get("config").map(String::as_str), Some("dev.toml"));
+        assert!(matches!(parse_args(&args(&["profile"])), Err(ConfigError::InvalidArgument(_))));
+        assert!(matches!(parse_args(&args(&["--profile"])), Err(ConfigError::InvalidArgument(_))));
+    }
+
+    #[test]
+    fn rejects_unknown_profiles() {
+        let mut file: NamedTempFile = NamedTempFile::new().unwrap();
+        write!(file, "{}", CONFIG).unwrap();
+        let path: String = file.path().to_str().unwrap().to_string();
+        let result = Config::load_from(&args(&["--config", &path, "--profile", "staging"]));
+        assert!(matches!(result, Err(ConfigError::UnknownProfile(name)) if name == "staging"));
+        let missing = Config::load_from(&args(&["--config", "does-not-exist.toml"]));
+        assert!(matches!(missing, Err(ConfigError::Io(_, _))));
+    }
+}
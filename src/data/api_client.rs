@@ -1,9 +1,10 @@
 use reqwest::{header::HeaderMap, Response, Error};
 use log;
+use super::secret::Secret;
 
 #[derive(Clone)]
 pub struct APIClient {
-    pub db_token: String,
+    pub db_token: Secret,
     pub workspace_name: String,
 }
 
@@ -13,7 +14,7 @@ impl APIClient {
         let client: reqwest::Client = reqwest::Client::new();
         let mut headers: HeaderMap = HeaderMap::new();
         headers.insert("content-type", "application/json".parse().unwrap());
-        headers.insert("Authorization", format!("Bearer {}", &self.db_token).parse().unwrap());
+        headers.insert("Authorization", format!("Bearer {}", self.db_token.expose_secret()).parse().unwrap());
         
 
         let response: Response = client.get(url)
@@ -39,4 +40,44 @@ impl APIClient {
 
         Ok(response)
     }
+
+    pub async fn post(&self, url: &str, body: &serde_json::Value) -> Result<Response, Error> {
+        let client: reqwest::Client = reqwest::Client::new();
+        let mut headers: HeaderMap = HeaderMap::new();
+        headers.insert("content-type", "application/json".parse().unwrap());
+        headers.insert("Authorization", format!("Bearer {}", self.db_token.expose_secret()).parse().unwrap());
+
+        let response: Response = client.post(url)
+        .headers(headers)
+        .json(body)
+        .send()
+        .await?;
+
+        // Check if the response status code is not 200
+        if !response.status().is_success() {
+            log::error!("POST request to {} failed with status code: {}", url, response.status());
+        }
+
+        Ok(response)
+    }
+
+    pub async fn patch(&self, url: &str, body: &serde_json::Value) -> Result<Response, Error> {
+        let client: reqwest::Client = reqwest::Client::new();
+        let mut headers: HeaderMap = HeaderMap::new();
+        headers.insert("content-type", "application/json".parse().unwrap());
+        headers.insert("Authorization", format!("Bearer {}", self.db_token.expose_secret()).parse().unwrap());
+
+        let response: Response = client.patch(url)
+        .headers(headers)
+        .json(body)
+        .send()
+        .await?;
+
+        // Check if the response status code is not 200
+        if !response.status().is_success() {
+            log::error!("PATCH request to {} failed with status code: {}", url, response.status());
+        }
+
+        Ok(response)
+    }
 }
\ No newline at end of file
@@ -0,0 +1,123 @@
+use crate::sql::sql_client::SqlClient;
+use super::permissions::BoxError;
+use serde::Serialize;
+use sqlx::prelude::FromRow;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+
+// a single read or write attempt against a table
+#[derive(Debug, Serialize, Clone, FromRow)]
+pub struct AuditRecord {
+    pub principal: String,
+    pub table_name: String,
+    pub operation: String,
+    pub decision: String, // allowed, denied or error
+    pub reason: Option<String>,
+    pub hidden_columns: Option<String>,
+    pub rows_returned: Option<i64>,
+    pub bytes_read: Option<i64>,
+    pub table_version: Option<i64>, // the delta version that was read
+    pub started_at: i64, // epoch milliseconds
+    pub duration_ms: i64,
+}
+impl AuditRecord {
+    // starts the record of a read or write, decisions are allowed unless denied or the read fails
+    pub fn start(principal: &str, table_name: &str, operation: &str) -> Self {
+        AuditRecord {
+            principal: principal.to_string(),
+            table_name: table_name.to_string(),
+            operation: operation.to_string(),
+            decision: String::from("allowed"),
+            reason: None,
+            hidden_columns: None,
+            rows_returned: None,
+            bytes_read: None,
+            table_version: None,
+            started_at: now_millis(),
+            duration_ms: 0,
+        }
+    }
+
+    pub fn deny(&mut self, reason: &str) {
+        self.decision = String::from("denied");
+        self.reason = Some(reason.to_string());
+    }
+
+    // completes the record once the read returns, failed reads that were not denied are errors
+    pub fn finish<T, E: std::fmt::Display>(&mut self, result: &Result<T, E>) {
+        self.duration_ms = now_millis() - self.started_at;
+        if let Err(e) = result {
+            if self.decision != "denied" {
+                self.decision = String::from("error");
+                self.reason = Some(e.to_string());
+            }
+        }
+    }
+}
+
+// per principal, table and decision counts used for compliance reports
+#[derive(Debug, Serialize, Clone, FromRow)]
+pub struct AuditSummary {
+    pub principal: String,
+    pub table_name: String,
+    pub decision: String,
+    pub attempts: i64,
+    pub rows_returned: Option<i64>,
+    pub bytes_read: Option<i64>,
+    pub first_attempt: i64,
+    pub last_attempt: i64,
+}
+
+
+// writes audit records to the append only access_audit_log table and optionally a json lines file
+#[derive(Clone)]
+pub struct AuditLogger {
+    sql_client: SqlClient,
+    jsonl_path: Option<String>,
+}
+impl AuditLogger {
+    pub fn new(sql_client: SqlClient) -> Self {
+        AuditLogger { sql_client, jsonl_path: None }
+    }
+
+    /// Also appends every record to a json lines file.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path of the json lines file, created if it does not exist
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let audit_logger: AuditLogger = AuditLogger::new(sql_client.clone()).with_jsonl_file("access_audit.jsonl");
+    /// ```
+    pub fn with_jsonl_file(mut self, path: &str) -> Self {
+        self.jsonl_path = Some(path.to_string());
+        self
+    }
+
+    pub async fn record(&self, record: &AuditRecord) -> Result<(), BoxError> {
+        log::info!("Audit: {} {} {} - {}", record.principal, record.operation, record.table_name, record.decision);
+        self.sql_client.write_access_audit(record).await?;
+
+        if let Some(path) = &self.jsonl_path {
+            let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+            writeln!(file, "{}", serde_json::to_string(record)?)?;
+        }
+        Ok(())
+    }
+
+    pub async fn list(&self, principal: Option<&str>, table_name: Option<&str>, since: Option<i64>, until: Option<i64>) -> Result<Vec<AuditRecord>, BoxError> {
+        Ok(self.sql_client.list_access_audit(principal, table_name, since, until).await?)
+    }
+
+    pub async fn summary(&self, since: Option<i64>, until: Option<i64>) -> Result<Vec<AuditSummary>, BoxError> {
+        Ok(self.sql_client.access_audit_summary(since, until).await?)
+    }
+}
+
+fn now_millis() -> i64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis() as i64
+}
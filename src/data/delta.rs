@@ -1,23 +1,579 @@
 use deltalake::datafusion::execution::context::SessionState;
+use deltalake::datafusion::datasource::{MemTable, TableProvider};
+use deltalake::datafusion::physical_plan::ExecutionPlan;
 //https://github.com/delta-io/delta-rs
-use deltalake::{open_table_with_storage_options, DeltaTableError, datafusion::prelude::*, Path, ObjectStore};
-use deltalake::azure::register_handlers;
+use deltalake::{DeltaOps, DeltaTableBuilder, DeltaTableError, datafusion::prelude::*, Path, ObjectStore};
+use deltalake::protocol::SaveMode;
+use deltalake::operations::delete::DeleteMetrics;
+use deltalake::operations::merge::{MergeBuilder, MergeMetrics};
+use deltalake::kernel::{DataType as DeltaDataType, PrimitiveType, StructType};
 use std::sync::Arc;
 use polars::prelude::*;
+// the metastore module also has a Schema
+use polars::prelude::Schema;
 use std::io::Cursor;
-use std::convert::TryFrom;
+use std::collections::HashMap;
 use bytes::Bytes; 
 use futures;
+use futures::stream::{BoxStream, Stream, StreamExt};
+use deltalake::arrow::array::{new_null_array, ArrayRef, BooleanArray, Scalar, StringArray};
+use deltalake::arrow::compute::{and, cast, cast_with_options, filter_record_batch, CastOptions};
+use deltalake::arrow::compute::kernels::cmp;
+use deltalake::arrow::datatypes::{DataType as ArrowDataType, Field as ArrowField, Schema as ArrowSchema};
+use deltalake::arrow::error::ArrowError;
+use deltalake::arrow::record_batch::RecordBatch;
+use deltalake::parquet::arrow::arrow_reader::{ArrowReaderOptions, ParquetRecordBatchReaderBuilder};
+use deltalake::parquet::arrow::ProjectionMask;
 
 use super::permissions::*; 
 use super::metastore::*;
+use super::audit::{AuditLogger, AuditRecord};
+use super::storage::{StorageOptions, StorageScheme};
+use super::filter::{Filter, FilterOp, FileStats};
+use super::protocol::{check_reader_features, load_deleted_rows, ColumnMapping};
+use deltalake::kernel::{Action, CommitInfo, DeletionVectorDescriptor};
+use roaring::RoaringTreemap;
+use tokio::runtime::RuntimeFlavor;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicI64, Ordering as AtomicOrdering};
+use std::task::{Context, Poll};
+use tokio::sync::Mutex;
+use serde::Serialize;
+use sqlx::prelude::FromRow;
 
+// vended credentials are refreshed when they expire within this window
+const CREDENTIAL_REFRESH_BUFFER_MS: i64 = 5 * 60 * 1000;
+// table property that makes writers record row level changes
+const CHANGE_DATA_FEED: &str = "delta.enableChangeDataFeed";
+// columns added to change data feed rows
+const CHANGE_TYPE: &str = "_change_type";
+const COMMIT_VERSION: &str = "_commit_version";
+const COMMIT_TIMESTAMP: &str = "_commit_timestamp";
+// table properties that make writers record the commit time in the commit itself
+const IN_COMMIT_TIMESTAMPS: &str = "delta.enableInCommitTimestamps";
+const IN_COMMIT_TIMESTAMP_VERSION: &str = "delta.inCommitTimestampEnablementVersion";
+// rows per record batch when streaming arrow batches
+const RECORD_BATCH_SIZE: usize = 8192;
+
+
+// how the reader handles tables protected by unity catalog row filters and column masks
+#[derive(Debug, Clone, PartialEq)]
+pub enum PolicyEnforcement {
+    Refuse, // never return data from a protected table
+    Apply, // apply supported filter and mask functions through datafusion, refuse the rest
+}
+
+// how a read opens the table, which decides what happens to tables with row filters or column masks
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ReadPath {
+    Files, // the parquet files are decoded here, so protected tables are refused
+    Datafusion, // the policies are applied in the query unless the policy enforcement refuses them
+    Metadata, // only the delta log is read, so the policies do not stop a principal who can read the table
+}
+
+// which version of a table to read, the latest version when neither is set, and which columns and rows
+#[derive(Debug, Clone, Default)]
+pub struct ReadOptions {
+    pub version: Option<i64>,
+    pub timestamp: Option<String>, // RFC 3339, i.e. 2024-01-01T00:00:00Z
+    pub columns: Option<Vec<String>>, // all columns when None
+    pub filters: Vec<Filter>, // combined with AND
+}
+impl ReadOptions {
+    pub fn new() -> Self {
+        ReadOptions::default()
+    }
+
+    /// Reads the table as of a commit version.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let options: ReadOptions = ReadOptions::new().with_version(12);
+    /// ```
+    pub fn with_version(mut self, version: i64) -> Self {
+        self.version = Some(version);
+        self
+    }
+
+    /// Reads the table as it was at a point in time, the latest commit at or before the timestamp.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let options: ReadOptions = ReadOptions::new().with_timestamp("2024-01-01T00:00:00Z");
+    /// ```
+    pub fn with_timestamp(mut self, timestamp: &str) -> Self {
+        self.timestamp = Some(timestamp.to_string());
+        self
+    }
+
+    /// Reads only the given columns, only these are downloaded from each parquet file.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let options: ReadOptions = ReadOptions::new().with_columns(&["date", "sku", "quantity"]);
+    /// ```
+    pub fn with_columns(mut self, columns: &[&str]) -> Self {
+        self.columns = Some(columns.iter().map(|c| c.to_string()).collect());
+        self
+    }
+
+    /// Keeps only rows matching the filter, files are skipped using partition values and min/max stats from the delta log.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let options: ReadOptions = ReadOptions::new().with_filter(Filter::eq("date", "2024-01-01")).with_filter(Filter::gt("quantity", 10));
+    /// ```
+    pub fn with_filter(mut self, filter: Filter) -> Self {
+        self.filters.push(filter);
+        self
+    }
+}
+
+// the commits a change data feed read covers, starting versions and timestamps are inclusive
+// the feed ends at the latest version unless an ending version or timestamp is set
+#[derive(Debug, Clone, Default)]
+pub struct ChangeFeedOptions {
+    pub starting_version: Option<i64>,
+    pub starting_timestamp: Option<String>, // RFC 3339, i.e. 2024-01-01T00:00:00Z
+    pub ending_version: Option<i64>,
+    pub ending_timestamp: Option<String>,
+}
+impl ChangeFeedOptions {
+    /// Reads the changes committed from the version on.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let options: ChangeFeedOptions = ChangeFeedOptions::from_version(5).with_ending_version(10);
+    /// ```
+    pub fn from_version(version: i64) -> Self {
+        ChangeFeedOptions { starting_version: Some(version), ..Default::default() }
+    }
+
+    /// Reads the changes committed at or after the timestamp.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let options: ChangeFeedOptions = ChangeFeedOptions::from_timestamp("2024-01-01T00:00:00Z");
+    /// ```
+    pub fn from_timestamp(timestamp: &str) -> Self {
+        ChangeFeedOptions { starting_timestamp: Some(timestamp.to_string()), ..Default::default() }
+    }
+
+    pub fn with_ending_version(mut self, version: i64) -> Self {
+        self.ending_version = Some(version);
+        self
+    }
+
+    pub fn with_ending_timestamp(mut self, timestamp: &str) -> Self {
+        self.ending_timestamp = Some(timestamp.to_string());
+        self
+    }
+
+    // the table is loaded at the end of the feed, its schema is the schema of the output
+    // the ending timestamp is resolved by the feed against its own commit timestamps
+    fn ending_read_options(&self) -> ReadOptions {
+        ReadOptions { version: self.ending_version, ..ReadOptions::default() }
+    }
+}
+
+// how a write changes the table, the other deltalake save modes are not supported
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WriteMode {
+    Append, // adds the rows to the table
+    Overwrite, // replaces every row of the table
+}
+impl WriteMode {
+    fn save_mode(&self) -> SaveMode {
+        match self {
+            WriteMode::Append => SaveMode::Append,
+            WriteMode::Overwrite => SaveMode::Overwrite,
+        }
+    }
+}
+
+// what happens to target rows in a merge, predicates and expressions are sql that refers to the `source` and `target` aliases
+#[derive(Debug, Clone)]
+pub enum MergeClause {
+    MatchedUpdate { predicate: Option<String>, set: Vec<(String, String)> }, // every source column of the table when set is empty
+    MatchedDelete { predicate: Option<String> },
+    NotMatchedInsert { predicate: Option<String>, values: Vec<(String, String)> }, // every source column of the table when values is empty
+    NotMatchedBySourceDelete { predicate: Option<String> },
+}
+
+// how source rows are matched to target rows and the clauses applied, clauses of the same kind are tried in order
+#[derive(Debug, Clone, Default)]
+pub struct MergeOptions {
+    pub keys: Vec<String>, // rows match when every key is equal
+    pub clauses: Vec<MergeClause>,
+}
+impl MergeOptions {
+    /// Matches source and target rows on the key columns.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let options: MergeOptions = MergeOptions::new(&["sku", "date"]).when_matched_update_all().when_not_matched_insert_all();
+    /// ```
+    pub fn new(keys: &[&str]) -> Self {
+        MergeOptions { keys: keys.iter().map(|k| k.to_string()).collect(), clauses: Vec::new() }
+    }
+
+    /// Updates matched rows with every source column the table has, columns the source leaves out keep their values.
+    pub fn when_matched_update_all(self) -> Self {
+        self.with_clause(MergeClause::MatchedUpdate { predicate: None, set: Vec::new() })
+    }
+
+    /// Updates the given columns of matched rows that also match the predicate.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let options: MergeOptions = MergeOptions::new(&["sku"]).when_matched_update(Some("source.updated_at > target.updated_at"), &[("quantity", "source.quantity")]);
+    /// ```
+    pub fn when_matched_update(self, predicate: Option<&str>, set: &[(&str, &str)]) -> Self {
+        self.with_clause(MergeClause::MatchedUpdate {
+            predicate: predicate.map(|p| p.to_string()),
+            set: set.iter().map(|(c, e)| (c.to_string(), e.to_string())).collect(),
+        })
+    }
+
+    /// Deletes matched rows that also match the predicate, every matched row when None.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let options: MergeOptions = MergeOptions::new(&["sku"]).when_matched_delete(Some("source.is_deleted = true"));
+    /// ```
+    pub fn when_matched_delete(self, predicate: Option<&str>) -> Self {
+        self.with_clause(MergeClause::MatchedDelete { predicate: predicate.map(|p| p.to_string()) })
+    }
+
+    /// Inserts source rows without a match, with every source column the table has.
+    pub fn when_not_matched_insert_all(self) -> Self {
+        self.with_clause(MergeClause::NotMatchedInsert { predicate: None, values: Vec::new() })
+    }
+
+    /// Inserts source rows without a match that match the predicate, with the given column values.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let options: MergeOptions = MergeOptions::new(&["sku"]).when_not_matched_insert(None, &[("sku", "source.sku"), ("quantity", "0")]);
+    /// ```
+    pub fn when_not_matched_insert(self, predicate: Option<&str>, values: &[(&str, &str)]) -> Self {
+        self.with_clause(MergeClause::NotMatchedInsert {
+            predicate: predicate.map(|p| p.to_string()),
+            values: values.iter().map(|(c, e)| (c.to_string(), e.to_string())).collect(),
+        })
+    }
+
+    /// Deletes target rows no source row matches, i.e. to make the table mirror the source.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let options: MergeOptions = MergeOptions::new(&["sku"]).when_matched_update_all().when_not_matched_insert_all().when_not_matched_by_source_delete(None);
+    /// ```
+    pub fn when_not_matched_by_source_delete(self, predicate: Option<&str>) -> Self {
+        self.with_clause(MergeClause::NotMatchedBySourceDelete { predicate: predicate.map(|p| p.to_string()) })
+    }
+
+    pub fn with_clause(mut self, clause: MergeClause) -> Self {
+        self.clauses.push(clause);
+        self
+    }
+
+    // the join condition on the key columns
+    fn predicate(&self) -> String {
+        self.keys.iter()
+            .map(|k| format!("target.\"{}\" = source.\"{}\"", k, k))
+            .collect::<Vec<String>>()
+            .join(" AND ")
+    }
+}
+
+// what a merge or delete changed
+#[derive(Debug, Clone, Default)]
+pub struct OperationMetrics {
+    pub version: i64, // the table version the operation committed
+    pub rows_inserted: usize,
+    pub rows_updated: usize,
+    pub rows_deleted: usize,
+    pub rows_copied: usize, // unchanged rows rewritten into new files
+    pub files_added: usize,
+    pub files_removed: usize,
+    pub execution_time_ms: u64,
+}
+
+// a commit of the delta log
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct TableCommit {
+    pub version: i64,
+    pub commit_timestamp: Option<i64>, // epoch milliseconds
+    pub operation: Option<String>, // i.e. WRITE, MERGE, DELETE, OPTIMIZE
+    pub operation_parameters: Option<String>, // json object
+    pub user_name: Option<String>,
+}
+
+// the latest version of a table as recorded in the delta log
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct TableDetails {
+    pub table_name: String,
+    pub table_version: i64,
+    pub last_commit_timestamp: Option<i64>, // epoch milliseconds
+    pub num_files: i64,
+    pub size_in_bytes: i64,
+    pub partition_columns: String, // comma separated
+    pub min_reader_version: i32,
+    pub min_writer_version: i32,
+    pub reader_features: Option<String>, // comma separated, only set for reader version 3
+    pub writer_features: Option<String>, // comma separated, only set for writer version 7
+}
+
+// a data file of the table version being read, with its path relative to the table root
+#[derive(Debug, Clone)]
+struct DeltaFile {
+    path: Path,
+    partition_values: HashMap<String, Option<String>>, // keyed by logical column name
+    stats: Option<FileStats>,
+    deletion_vector: Option<DeletionVectorDescriptor>,
+}
+
+// a permission checked read of a table version, resolved from the delta log but not yet downloaded
+struct PreparedRead {
+    object_store: Arc<dyn ObjectStore>,
+    table_uri: String,
+    table_schema: StructType,
+    column_mapping: ColumnMapping,
+    partition_columns: Vec<String>,
+    files: Vec<DeltaFile>,
+    hidden: Vec<String>,
+}
+impl PreparedRead {
+    // downloads files in order with at most max_in_flight requests running, nothing is fetched until the stream is polled
+    // each file comes with the rows deleted from it by its deletion vector
+    fn download(&self, max_in_flight: usize) -> impl Stream<Item = Result<(DeltaFile, Bytes, Option<RoaringTreemap>), DeltaTableError>> + Send + 'static {
+        let object_store: Arc<dyn ObjectStore> = Arc::clone(&self.object_store);
+        let table_uri: String = self.table_uri.clone();
+        futures::stream::iter(self.files.clone())
+            .map(move |file| {
+                let object_store: Arc<dyn ObjectStore> = Arc::clone(&object_store);
+                let table_uri: String = table_uri.clone();
+                async move {
+                    log::info!("Loading file: {}", file.path);
+                    let bytes: Bytes = object_store.get(&file.path).await?.bytes().await?;
+                    let deleted: Option<RoaringTreemap> = match &file.deletion_vector {
+                        Some(dv) => Some(load_deleted_rows(&object_store, &table_uri, dv).await?),
+                        None => None,
+                    };
+                    Ok::<(DeltaFile, Bytes, Option<RoaringTreemap>), DeltaTableError>((file, bytes, deleted))
+                }
+            })
+            .buffered(max_in_flight.max(1))
+    }
+}
+
+// a streamed read whose audit record is written once the stream ends or is dropped, with the rows the consumer received
+// and the first error the stream returned
+struct AuditedStream<T> {
+    inner: BoxStream<'static, Result<T, DeltaTableError>>,
+    audit: Option<AuditRecord>, // taken when the record is written
+    audit_logger: Option<AuditLogger>,
+    bytes_read: Arc<AtomicI64>, // added to by the downloads
+    error: Option<String>,
+    rows: fn(&T) -> usize,
+}
+impl<T> AuditedStream<T> {
+    fn record(&mut self) {
+        let mut audit: AuditRecord = match self.audit.take() {
+            Some(audit) => audit,
+            None => return,
+        };
+        audit.bytes_read = Some(self.bytes_read.load(AtomicOrdering::Relaxed));
+        let result: Result<(), String> = match self.error.take() {
+            Some(e) => Err(e),
+            None => Ok(()),
+        };
+        audit.finish(&result);
+        let audit_logger: AuditLogger = match self.audit_logger.clone() {
+            Some(audit_logger) => audit_logger,
+            None => return,
+        };
+        // neither poll_next nor drop can wait, so the record is written in the background
+        match tokio::runtime::Handle::try_current() {
+            Ok(handle) => {
+                handle.spawn(async move {
+                    if let Err(e) = audit_logger.record(&audit).await {
+                        log::error!("Failed to write audit record for {}: {}", audit.table_name, e);
+                    }
+                });
+            }
+            Err(_) => log::error!("Failed to write audit record for {}: no tokio runtime", audit.table_name),
+        }
+    }
+}
+impl<T> Stream for AuditedStream<T> {
+    type Item = Result<T, DeltaTableError>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let item: Option<Result<T, DeltaTableError>> = futures::ready!(self.inner.poll_next_unpin(cx));
+        match &item {
+            Some(Ok(value)) => {
+                let rows: i64 = (self.rows)(value) as i64;
+                if let Some(audit) = self.audit.as_mut() {
+                    audit.rows_returned = Some(audit.rows_returned.unwrap_or(0) + rows);
+                }
+            }
+            Some(Err(e)) => {
+                if self.error.is_none() {
+                    self.error = Some(e.to_string());
+                }
+            }
+            None => self.record(),
+        }
+        Poll::Ready(item)
+    }
+}
+impl<T> Drop for AuditedStream<T> {
+    fn drop(&mut self) {
+        self.record();
+    }
+}
+
+// sums the bytes the parquet scans of a plan read from storage
+fn bytes_scanned(plan: &dyn ExecutionPlan) -> usize {
+    let scanned: usize = plan.metrics()
+        .and_then(|metrics| metrics.sum_by_name("bytes_scanned"))
+        .map(|value| value.as_usize())
+        .unwrap_or(0);
+    scanned + plan.children().iter().map(|child| bytes_scanned(child.as_ref())).sum::<usize>()
+}
+
+// a polars scan over the data files of a table version, files are downloaded when the lazy frame is collected
+// the object store keeps the storage credentials the scan was created with
+struct DeltaScan {
+    runtime: tokio::runtime::Handle,
+    object_store: Arc<dyn ObjectStore>,
+    table_uri: String,
+    files: Vec<DeltaFile>,
+    table_schema: StructType,
+    column_mapping: ColumnMapping,
+    partition_columns: Vec<String>,
+    schema: SchemaRef, // without hidden columns
+}
+impl DeltaScan {
+    // polars calls the scan synchronously, the download runs on the runtime the scan was created on
+    // block_in_place panics on a current thread runtime, and a download there would wait on the thread blocked in collect
+    fn block_on<F: std::future::Future>(&self, future: F) -> PolarsResult<F::Output> {
+        let current_thread: bool = self.runtime.runtime_flavor() == RuntimeFlavor::CurrentThread
+            || tokio::runtime::Handle::try_current().is_ok_and(|h| h.runtime_flavor() == RuntimeFlavor::CurrentThread);
+        if current_thread {
+            return Err(PolarsError::ComputeError("Delta table scans must be collected on a multi threaded tokio runtime.".into()));
+        }
+        Ok(tokio::task::block_in_place(|| self.runtime.block_on(future)))
+    }
+
+    fn fetch(&self, path: &Path) -> PolarsResult<Bytes> {
+        let object_store: Arc<dyn ObjectStore> = Arc::clone(&self.object_store);
+        self.block_on(async move { object_store.get(path).await?.bytes().await })?
+            .map_err(|e| PolarsError::ComputeError(format!("Failed to load file {}: {}", path, e).into()))
+    }
+
+    fn fetch_deleted_rows(&self, file: &DeltaFile) -> PolarsResult<Option<RoaringTreemap>> {
+        let descriptor: &DeletionVectorDescriptor = match &file.deletion_vector {
+            Some(dv) => dv,
+            None => return Ok(None),
+        };
+        self.block_on(load_deleted_rows(&self.object_store, &self.table_uri, descriptor))?
+            .map(Some)
+            .map_err(|e| PolarsError::ComputeError(format!("Failed to load deletion vector of {}: {}", file.path, e).into()))
+    }
+}
+impl AnonymousScan for DeltaScan {
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+
+    fn schema(&self, _infer_schema_length: Option<usize>) -> PolarsResult<SchemaRef> {
+        Ok(self.schema.clone())
+    }
+
+    fn allows_predicate_pushdown(&self) -> bool {
+        true
+    }
+
+    fn allows_projection_pushdown(&self) -> bool {
+        true
+    }
+
+    fn allows_slice_pushdown(&self) -> bool {
+        true
+    }
+
+    fn scan(&self, scan_opts: AnonymousScanArgs) -> PolarsResult<polars::prelude::DataFrame> {
+        // hidden columns are never part of the output as they are not in the schema
+        let columns: Vec<String> = match &scan_opts.with_columns {
+            Some(columns) => columns.as_ref().clone(),
+            None => self.schema.iter_names().map(|n| n.to_string()).collect(),
+        };
+        let parquet_columns: Vec<String> = columns.iter()
+            .filter(|c| !self.partition_columns.contains(c))
+            .map(|c| self.column_mapping.physical(c))
+            .collect();
+        let parquet_columns: Option<Vec<String>> = if parquet_columns.is_empty() { None } else { Some(parquet_columns) };
+        let expected: Schema = expected_schema(&self.table_schema, Some(&columns));
+
+        let mut df: Option<polars::prelude::DataFrame> = None;
+        let mut rows: usize = 0;
+        for file in &self.files {
+            if scan_opts.n_rows.is_some_and(|n| rows >= n) {
+                break;
+            }
+            log::info!("Scanning file: {}", file.path);
+            let bytes: Bytes = self.fetch(&file.path)?;
+            let deleted: Option<RoaringTreemap> = self.fetch_deleted_rows(file)?;
+            let mut file_df: polars::prelude::DataFrame = read_polars_file(file, bytes, parquet_columns.clone(), deleted.as_ref(), &self.column_mapping, &self.table_schema, &expected)
+                .map_err(|e| PolarsError::ComputeError(e.to_string().into()))?;
+            if let Some(predicate) = &scan_opts.predicate {
+                file_df = file_df.lazy().filter(predicate.clone()).collect()?;
+            }
+            let file_df: polars::prelude::DataFrame = file_df.select(&columns)?;
+            rows += file_df.height();
+            df = match df {
+                Some(mut stacked) => {
+                    stacked.vstack_mut(&file_df)?;
+                    Some(stacked)
+                }
+                None => Some(file_df),
+            };
+        }
+
+        let df: polars::prelude::DataFrame = match df {
+            Some(df) => df,
+            None => polars::prelude::DataFrame::from(self.schema.as_ref()).select(&columns)?,
+        };
+        Ok(match scan_opts.n_rows {
+            Some(n) => df.head(Some(n)),
+            None => df,
+        })
+    }
+}
 
 pub struct DeltaLakeReader {
-    storage_credentials: AzureDataLakeGen2Options,
+    storage_credentials: StorageOptions,
     permissions_client: Permissions,
     metastore_client: MetastoreClient,
     principal: String,
+    policy_enforcement: PolicyEnforcement,
+    audit_logger: Option<AuditLogger>,
+    credential_vending: bool,
+    credential_cache: Arc<Mutex<HashMap<String, TemporaryTableCredentials>>>, // keyed by table_id
 
 }
 impl DeltaLakeReader {
@@ -25,7 +581,7 @@ impl DeltaLakeReader {
     ///
     /// # Arguments
     ///
-    /// * `storage_credentials` - The credentials used to authenticate against azure storage
+    /// * `storage_credentials` - The credentials used to authenticate against storage, chosen per table by its storage location
     /// * `permissions_client` - Permissions Object to validate user permissions against unity catalog 
     /// * `metastore_client` - Metastore Client object to interact with Unity Catalog APIs for data objects. 
     /// * `principal` - The active user's username. 
@@ -33,83 +589,274 @@ impl DeltaLakeReader {
     /// # Examples
     ///
     /// ```
-    ///     let reader: DeltaLakeReader = DeltaLakeReader::new(storage_options, permissions_client.clone(), metastore_client.clone(), String::from(principal));
+    ///     let reader: DeltaLakeReader = DeltaLakeReader::new(config.storage.clone(), permissions_client.clone(), metastore_client.clone(), String::from(principal));
     /// ```
-    pub fn new(storage_credentials: AzureDataLakeGen2Options, permissions_client: Permissions, metastore_client: MetastoreClient, principal: String) -> Self {
+    pub fn new(storage_credentials: StorageOptions, permissions_client: Permissions, metastore_client: MetastoreClient, principal: String) -> Self {
         let reader: DeltaLakeReader = DeltaLakeReader {
             storage_credentials,
             permissions_client,
             metastore_client,
             principal,
+            policy_enforcement: PolicyEnforcement::Refuse,
+            audit_logger: None,
+            credential_vending: false,
+            credential_cache: Arc::new(Mutex::new(HashMap::new())),
         };
 
         // Call the register_handlers function
-        register_handlers(None);
+        StorageOptions::register_handlers();
 
         reader
     }
 
+    /// Sets how tables with row filters or column masks are handled, tables are refused by default.
+    ///
+    /// # Arguments
+    ///
+    /// * `policy_enforcement` - Refuse protected tables or apply their policies
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let reader: DeltaLakeReader = DeltaLakeReader::new(storage_options, permissions_client, metastore_client, principal).with_policy_enforcement(PolicyEnforcement::Apply);
+    /// ```
+    pub fn with_policy_enforcement(mut self, policy_enforcement: PolicyEnforcement) -> Self {
+        self.policy_enforcement = policy_enforcement;
+        self
+    }
+
+    /// Records every read attempt, allowed or not, with the audit logger.
+    ///
+    /// # Arguments
+    ///
+    /// * `audit_logger` - The audit logger to record reads with
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let reader: DeltaLakeReader = DeltaLakeReader::new(storage_options, permissions_client, metastore_client, principal).with_audit_logger(AuditLogger::new(sql_client.clone()));
+    /// ```
+    pub fn with_audit_logger(mut self, audit_logger: AuditLogger) -> Self {
+        self.audit_logger = Some(audit_logger);
+        self
+    }
+
+    /// Reads storage with short lived, table scoped credentials from Unity Catalog instead of the reader's storage credentials.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let reader: DeltaLakeReader = DeltaLakeReader::new(storage_options, permissions_client, metastore_client, principal).with_credential_vending();
+    /// ```
+    pub fn with_credential_vending(mut self) -> Self {
+        self.credential_vending = true;
+        self
+    }
+
+    // storage options used to open a table, vended credentials are cached per table until they are close to expiring
+    async fn storage_options(&self, uc_table: &Table, operation: &str) -> Result<HashMap<String, String>, DeltaTableError> {
+        table_storage_options(&self.storage_credentials, &self.permissions_client, self.credential_vending, &self.credential_cache, uc_table, operation).await
+    }
+
+    // loads the table at the version or timestamp requested by the read options
+    async fn open_table(&self, table_path: &str, storage_options: HashMap<String, String>, options: &ReadOptions) -> Result<deltalake::DeltaTable, DeltaTableError> {
+        let mut builder: DeltaTableBuilder = DeltaTableBuilder::from_uri(table_path).with_storage_options(storage_options);
+        match (options.version, &options.timestamp) {
+            (Some(_), Some(_)) => {
+                return Err(DeltaTableError::Generic(String::from("Only one of version or timestamp can be set.")));
+            }
+            (Some(version), None) => {
+                log::info!("Loading version {} of {}", version, table_path);
+                builder = builder.with_version(version);
+            }
+            (None, Some(timestamp)) => {
+                log::info!("Loading {} as of {}", table_path, timestamp);
+                builder = builder.with_datestring(timestamp)?;
+            }
+            (None, None) => {}
+        }
+        builder.load().await
+    }
+
+    // a failure to write the audit record is logged but does not fail the read
+    async fn record_audit(&self, record: &AuditRecord) {
+        if let Some(audit_logger) = &self.audit_logger {
+            if let Err(e) = audit_logger.record(record).await {
+                log::error!("Failed to write audit record for {}: {}", record.table_name, e);
+            }
+        }
+    }
+
     /// If the user has permission to read the table, then this function returns a datafusion dataframe. 
+    /// The query runs before the dataframe is returned, so the audit record has the rows and bytes read.
     ///
     /// # Arguments
     ///
     /// * `table_name` - The fully qualified table name
+    /// * `options` - The version or timestamp to read, the latest version by default
     ///
     /// # Examples
     ///
     /// ```
     /// let table_name: &str = "my_catalog.my_schema.my_table";
-    /// let df = reader.read_delta_table_as_datafusion(table_path).await.unwrap();
+    /// let df = reader.read_delta_table_as_datafusion(table_path, &ReadOptions::new()).await.unwrap();
     /// ```
-    pub async fn read_delta_table_as_datafusion(&self, table_name: &str) -> Result<deltalake::datafusion::prelude::DataFrame, DeltaTableError> {
-        let table_path = self.metastore_client.get_table(table_name).await.unwrap().storage_location.unwrap_or_default();
-        if !self.permissions_client.can_read(&table_name, &self.principal).await.unwrap() {
-            log::error!("Permissions of Object {} Denied.", table_name);
-            return Err(DeltaTableError::Generic(String::from("Permission Denied.")));
+    pub async fn read_delta_table_as_datafusion(&self, table_name: &str, options: &ReadOptions) -> Result<deltalake::datafusion::prelude::DataFrame, DeltaTableError> {
+        let mut audit: AuditRecord = AuditRecord::start(&self.principal, table_name, "read_delta_table_as_datafusion");
+        let result = self.datafusion_read(table_name, options, &mut audit).await;
+        audit.finish(&result);
+        self.record_audit(&audit).await;
+        result
+    }
+
+    async fn datafusion_read(&self, table_name: &str, options: &ReadOptions, audit: &mut AuditRecord) -> Result<deltalake::datafusion::prelude::DataFrame, DeltaTableError> {
+        let (uc_table, table): (Table, deltalake::DeltaTable) = self.open_authorized(table_name, options, ReadPath::Datafusion, audit).await?;
+        let qry: String = if uc_table.has_access_policies() {
+            self.policy_query(&uc_table).await?
+        } else {
+            String::from("SELECT * FROM loadtable")
+        };
+
+        // the policies are defined on the current schema, an older version read by time travel must still have every column they use
+        if uc_table.has_access_policies() {
+            let snapshot_columns: Vec<String> = TableProvider::schema(&table).fields().iter().map(|f| f.name().to_lowercase()).collect();
+            let missing: Vec<String> = policy_columns(&uc_table).into_iter()
+                .filter(|column| !snapshot_columns.contains(&column.to_lowercase()))
+                .collect();
+            if !missing.is_empty() {
+                log::error!("Version {} of {} does not have the policy columns {}.", table.version(), table_name, missing.join(", "));
+                audit.deny("table version does not have the columns its row filter or column masks use");
+                return Err(DeltaTableError::Generic(format!("Permission Denied: version {} of {} does not have the columns {} used by its row filter or column masks.", table.version(), table_name, missing.join(", "))));
+            }
+        }
+
+        // project out columns hidden from the principal by local column policies
+        let hidden: Vec<String> = self.authorize_columns(table_name, options, audit).await?;
+        let qry: String = if hidden.is_empty() {
+            qry
         } else {
-            log::info!("Validated Permissions on Object: {}", table_name);
+            let allowed: Vec<String> = TableProvider::schema(&table).fields().iter()
+                .map(|f| f.name().clone())
+                .filter(|name| !hidden.contains(name))
+                .map(|name| format!("\"{}\"", name))
+                .collect();
+            format!("SELECT {} FROM ({})", allowed.join(", "), qry)
+        };
+        let qry: String = read_options_query(qry, options);
+
+        let ctx: SessionContext = SessionContext::new();
+
+        ctx.register_table("loadtable", Arc::new(table))?;
 
-            log::info!("Reading Table: {}", table_path);
-            let table: deltalake::DeltaTable = open_table_with_storage_options(table_path, self.storage_credentials.to_hash_map()).await?;
+        let df: deltalake::datafusion::prelude::DataFrame = ctx.sql(&qry).await?;
 
-            let ctx: SessionContext = SessionContext::new();
+        // run the query here so the audit record has the rows returned and the bytes the scan read
+        let plan: Arc<dyn ExecutionPlan> = df.create_physical_plan().await?;
+        let batches: Vec<RecordBatch> = deltalake::datafusion::physical_plan::collect(Arc::clone(&plan), ctx.task_ctx()).await?;
+        audit.rows_returned = Some(batches.iter().map(|b| b.num_rows() as i64).sum());
+        audit.bytes_read = Some(bytes_scanned(plan.as_ref()) as i64);
+        let results: MemTable = MemTable::try_new(plan.schema(), vec![batches])?;
+        let df: deltalake::datafusion::prelude::DataFrame = ctx.read_table(Arc::new(results))?;
+        Ok(df)
+    }
 
-            ctx.register_table("loadtable", Arc::new(table)).unwrap();
+    // builds a query over `loadtable` that applies the table's column masks and row filter
+    async fn policy_query(&self, table: &Table) -> Result<String, DeltaTableError> {
+        let mut select_list: Vec<String> = Vec::new();
+        for column in table.columns.clone().unwrap_or_default() {
+            match &column.mask {
+                Some(mask) => {
+                    // the masked column is always the first argument of the mask function
+                    let mut args: Vec<String> = vec![column.name.clone()];
+                    args.extend(mask.using_column_names.clone().unwrap_or_default());
+                    let expr: String = self.policy_expression(&mask.function_name, &args).await?;
+                    log::info!("Masking column {} with {}", column.name, mask.function_name);
+                    select_list.push(format!("({}) AS \"{}\"", expr, column.name));
+                }
+                None => select_list.push(format!("\"{}\"", column.name)),
+            }
+        }
+        if select_list.is_empty() {
+            select_list.push(String::from("*"));
+        }
 
-            let df: deltalake::datafusion::prelude::DataFrame = ctx.sql("SELECT * FROM loadtable").await.unwrap();
-            return Ok(df);
+        let mut qry: String = format!("SELECT {} FROM loadtable", select_list.join(", "));
+        if let Some(filter) = &table.row_filter {
+            let expr: String = self.policy_expression(&filter.function_name, &filter.input_column_names).await?;
+            log::info!("Filtering rows with {}", filter.function_name);
+            qry.push_str(&format!(" WHERE {}", expr));
         }
+        Ok(qry)
+    }
 
-        
+    // inlines a sql udf body with its parameters bound to the given columns
+    // functions that are not simple sql expressions, or depend on group membership, are not supported
+    async fn policy_expression(&self, function_name: &str, args: &[String]) -> Result<String, DeltaTableError> {
+        let unsupported = |reason: &str| {
+            log::error!("Policy function {} is not supported: {}", function_name, reason);
+            DeltaTableError::Generic(format!("Policy function {} is not supported: {}", function_name, reason))
+        };
+
+        let function: FunctionInfo = self.metastore_client.get_function(function_name).await
+            .map_err(|e| DeltaTableError::Generic(format!("Failed to get policy function {}: {}", function_name, e)))?;
+        if function.routine_body.as_deref() != Some("SQL") {
+            return Err(unsupported("not a SQL function"));
+        }
+        let definition: String = function.routine_definition.clone().unwrap_or_default();
+        let mut body: &str = definition.trim();
+        if body.get(..7).is_some_and(|prefix| prefix.eq_ignore_ascii_case("RETURN ")) {
+            body = body[7..].trim();
+        }
+        let lowered: String = body.to_lowercase();
+        if body.is_empty() || lowered.starts_with("select") {
+            return Err(unsupported("body is not a scalar expression"));
+        }
+        if lowered.contains("is_account_group_member") || lowered.contains("is_member") {
+            return Err(unsupported("group membership checks cannot be evaluated locally"));
+        }
+
+        let mut params = function.input_params.and_then(|p| p.parameters).unwrap_or_default();
+        params.sort_by_key(|p| p.position);
+        if params.len() != args.len() {
+            return Err(unsupported("argument count does not match the function parameters"));
+        }
+
+        let mut replacements: HashMap<String, String> = HashMap::new();
+        for (param, column) in params.iter().zip(args.iter()) {
+            replacements.insert(param.name.to_lowercase(), format!("\"{}\"", column));
+        }
+        // the user functions are evaluated here as the principal is not a datafusion session user
+        let principal: String = format!("'{}'", self.principal.replace('\'', "''"));
+        let mut functions: HashMap<String, String> = HashMap::new();
+        functions.insert(String::from("current_user"), principal.clone());
+        functions.insert(String::from("session_user"), principal);
+        Ok(substitute_identifiers(body, &replacements, &functions))
     }
 
     /// Reads a delta table in a parallel fashion
     ///
     /// # Arguments
     ///
-    /// * `table_path` - The path to the table in cloud storage
+    /// * `table` - The delta table, loaded at the version being read
+    /// * `files` - The files to read, the bytes are returned in the same order
     ///
     /// # Examples
     ///
     /// ```
-    /// let table_path = self.metastore_client.get_table(table_name).await.unwrap().storage_location.unwrap_or_default();
-    /// let table_bytes = self.parallel_read_table_as_bytes(&table_path).await?;
+    /// let table: deltalake::DeltaTable = self.open_table(&table_path, storage_options, &ReadOptions::new()).await?;
+    /// let files: Vec<DeltaFile> = table_files(&table, &column_mapping)?;
+    /// let table_bytes = self.parallel_read_table_as_bytes(&table, &files).await?;
     /// ```
-    async fn parallel_read_table_as_bytes(&self, table_path: &str) -> Result<Vec<Bytes>, DeltaTableError> {
-        log::info!("Reading Table: {}", table_path);
-        let table: deltalake::DeltaTable = open_table_with_storage_options(table_path, self.storage_credentials.to_hash_map()).await?;
+    async fn parallel_read_table_as_bytes(&self, table: &deltalake::DeltaTable, files: &[DeltaFile]) -> Result<Vec<Bytes>, DeltaTableError> {
+        log::info!("Reading Table: {} version {}", table.table_uri(), table.version());
     
-        let files: Vec<String> = table.get_file_uris().unwrap().collect();
         let object_store: Arc<dyn ObjectStore> = table.object_store();
     
-        let futures: Vec<_> = files.into_iter().map(|file| {
+        let futures: Vec<_> = files.iter().map(|file| {
             let object_store = Arc::clone(&object_store);
             async move {
-                log::info!("Loading file: {}", file);
-                let parts: Vec<&str> = file.split('/').collect();
-                let file_name: &str = parts.last().unwrap();
-                let file_path: Path = Path::try_from(file_name.to_string()).unwrap();
-                let result = object_store.get(&file_path).await?;
+                log::info!("Loading file: {}", file.path);
+                let result = object_store.get(&file.path).await?;
                 let bytes = result.bytes().await?;
                 Ok::<Bytes, DeltaTableError>(bytes)
             }
@@ -126,34 +873,30 @@ impl DeltaLakeReader {
     ///
     /// # Arguments
     ///
-    /// * `table_path` - The path to the table in cloud storage
+    /// * `table` - The delta table, loaded at the version being read
+    /// * `files` - The files to read, the bytes are returned in the same order
     ///
     /// # Examples
     ///
     /// ```
-    /// let table_path = self.metastore_client.get_table(table_name).await.unwrap().storage_location.unwrap_or_default();
-    /// let table_bytes = self.read_table_as_bytes(&table_path).await?;
+    /// let table: deltalake::DeltaTable = self.open_table(&table_path, storage_options, &ReadOptions::new()).await?;
+    /// let files: Vec<DeltaFile> = table_files(&table, &column_mapping)?;
+    /// let table_bytes = self.read_table_as_bytes(&table, &files).await?;
     /// ```
-    async fn read_table_as_bytes(&self, table_path: &str) -> Result<Vec<Bytes>, DeltaTableError> { // return bytes
-        log::info!("Reading Table: {}", table_path);
-        let table: deltalake::DeltaTable = open_table_with_storage_options(table_path, self.storage_credentials.to_hash_map()).await?;
+    async fn read_table_as_bytes(&self, table: &deltalake::DeltaTable, files: &[DeltaFile]) -> Result<Vec<Bytes>, DeltaTableError> { // return bytes
+        log::info!("Reading Table: {} version {}", table.table_uri(), table.version());
 
         let mut table_bytes: Vec<Bytes> = Vec::default();
 
-        // get the files and storage object
-        let files: Vec<String> = table.get_file_uris().unwrap().collect();
+        // the object store is rooted at the table location
         let object_store: Arc<dyn ObjectStore> = table.object_store();
 
-        // foreach file we need only the file name 
-        // provide it to the storage objet to download into bytes 
+        // provide each path to the storage objet to download into bytes 
         // load the bytes into a Vec<Bytes>
         for file in files.iter() {
-            log::info!("Loading file: {}", file);
-            let parts: Vec<&str> = file.split('/').collect();
-            let file_name: &str = parts[parts.len()-1];
-            let file_path: Path = Path::try_from(format!("{}", file_name)).unwrap();
-            let result: deltalake::storage::GetResult = object_store.get(&file_path).await.unwrap();
-            let bytes: Bytes = result.bytes().await.unwrap();
+            log::info!("Loading file: {}", file.path);
+            let result: deltalake::storage::GetResult = object_store.get(&file.path).await?;
+            let bytes: Bytes = result.bytes().await?;
             table_bytes.push(bytes);
             
         }
@@ -161,63 +904,567 @@ impl DeltaLakeReader {
 
     }
 
-    /// If the user has permission to read the table, then this function returns a polars dataframe. 
+    /// If the user has permission to read the table, then this function returns a polars lazy frame over it.
+    /// Permissions are checked and the file list is resolved from the delta log up front, files are only downloaded on `collect`.
+    /// Projections, filters and limits in the query plan are pushed into the scan.
+    /// The lazy frame must be collected on a multi threaded tokio runtime, and with credential vending before the
+    /// credentials vended when the scan was created expire, they are not refreshed during `collect`.
     ///
     /// # Arguments
     ///
     /// * `table_name` - The fully qualified table name
-    /// * `parallel_read` - true/false argument to read the table serially or in parallel
+    /// * `options` - The version or timestamp to read, filters in the options also prune files up front
     ///
     /// # Examples
     ///
     /// ```
-    /// let table_name: &str = "my_catalog.my_schema.my_table";
-    /// let df = reader.read_delta_table_as_polars(table_path, true).await.unwrap();
+    /// let lf: LazyFrame = reader.scan_delta_table("my_catalog.my_schema.my_table", &ReadOptions::new()).await?;
+    /// let df = lf.filter(col("quantity").gt(lit(10))).select([col("sku"), col("quantity")]).limit(100).collect()?;
     /// ```
-    pub async fn read_delta_table_as_polars(&self, table_name: &str, parallel_read: bool) -> Result<polars::prelude::DataFrame, DeltaTableError> { //Result<polars::prelude::DataFrame, DeltaTableError> {        
-        // create empty DF - we will replace it later with the if/else
-        let table_path = self.metastore_client.get_table(table_name).await.unwrap().storage_location.unwrap_or_default();
-        let mut df: polars::prelude::DataFrame = polars::prelude::DataFrame::default();
-        let mut table_bytes: Vec<Bytes> = Vec::default();
+    pub async fn scan_delta_table(&self, table_name: &str, options: &ReadOptions) -> Result<LazyFrame, DeltaTableError> {
+        let mut audit: AuditRecord = AuditRecord::start(&self.principal, table_name, "scan_delta_table");
+        let result = self.polars_scan(table_name, options, &mut audit).await;
+        audit.finish(&result);
+        self.record_audit(&audit).await;
+        result
+    }
 
-        if !self.permissions_client.can_read(&table_name, &self.principal).await.unwrap() {
-            log::info!("Permissions of Object {} Denied.", table_name);
-            return Ok(df);
-        } else {
-            log::info!("Validated Permissions on Object: {}", table_name);
-            // get the table as a vector of bytes each index is a parquet file 
-            if parallel_read {
-                log::info!("Parallel reading table.");
-                table_bytes = self.parallel_read_table_as_bytes(&table_path).await?;
+    async fn polars_scan(&self, table_name: &str, options: &ReadOptions, audit: &mut AuditRecord) -> Result<LazyFrame, DeltaTableError> {
+        let prepared: PreparedRead = self.prepare_read(table_name, options, audit).await?;
+        let schema: SchemaRef = Arc::new(polars_schema(&prepared.table_schema, &prepared.hidden));
+
+        let scan: DeltaScan = DeltaScan {
+            runtime: tokio::runtime::Handle::current(),
+            object_store: prepared.object_store,
+            table_uri: prepared.table_uri,
+            files: prepared.files,
+            table_schema: prepared.table_schema,
+            column_mapping: prepared.column_mapping,
+            partition_columns: prepared.partition_columns,
+            schema: schema.clone(),
+        };
+        let args: ScanArgsAnonymous = ScanArgsAnonymous {
+            schema: Some(schema.clone()),
+            name: "delta_scan",
+            ..Default::default()
+        };
+        let mut lf: LazyFrame = LazyFrame::anonymous_scan(Arc::new(scan), args)
+            .map_err(|e| DeltaTableError::Generic(format!("Failed to create scan of {}: {}", table_name, e)))?;
+
+        for filter in &options.filters {
+            lf = lf.filter(filter.to_expr(schema.get(&filter.column)));
+        }
+        if let Some(columns) = &options.columns {
+            lf = lf.select(columns.iter().map(|c| polars::prelude::col(c)).collect::<Vec<polars::prelude::Expr>>());
+        }
+        Ok(lf)
+    }
+
+    /// If the user has permission to read the table, then this function returns a stream of polars dataframes, one per data file.
+    /// At most `max_in_flight` files are downloaded ahead of the consumer, so memory stays bounded however large the table is.
+    /// The read is audited when the stream ends or is dropped, with the rows and bytes the consumer received.
+    ///
+    /// # Arguments
+    ///
+    /// * `table_name` - The fully qualified table name
+    /// * `options` - The version, columns and filters to read
+    /// * `max_in_flight` - The number of files downloaded concurrently
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let mut stream = reader.stream_delta_table_as_polars("my_catalog.my_schema.my_table", &ReadOptions::new(), 4).await?;
+    /// while let Some(chunk) = stream.next().await {
+    ///     let df: DataFrame = chunk?;
+    /// }
+    /// ```
+    pub async fn stream_delta_table_as_polars(&self, table_name: &str, options: &ReadOptions, max_in_flight: usize) -> Result<BoxStream<'static, Result<polars::prelude::DataFrame, DeltaTableError>>, DeltaTableError> {
+        let mut audit: AuditRecord = AuditRecord::start(&self.principal, table_name, "stream_delta_table_as_polars");
+        let prepared: Result<PreparedRead, DeltaTableError> = self.prepare_read(table_name, options, &mut audit).await;
+        if prepared.is_err() {
+            audit.finish(&prepared);
+            self.record_audit(&audit).await;
+        }
+        let prepared: PreparedRead = prepared?;
+
+        let options: ReadOptions = options.clone();
+        let parquet_columns: Option<Vec<String>> = parquet_projection(&options, &prepared.partition_columns, &prepared.column_mapping);
+        let expected: Schema = expected_schema(&prepared.table_schema, read_columns(&options).as_deref());
+        let table_schema: StructType = prepared.table_schema.clone();
+        let column_mapping: ColumnMapping = prepared.column_mapping.clone();
+        let hidden: Vec<String> = prepared.hidden.clone();
+        let bytes_read: Arc<AtomicI64> = Arc::new(AtomicI64::new(0));
+        let downloaded_bytes: Arc<AtomicI64> = Arc::clone(&bytes_read);
+        let stream = prepared.download(max_in_flight)
+            .map(move |downloaded| {
+                let (file, bytes, deleted) = downloaded?;
+                downloaded_bytes.fetch_add(bytes.len() as i64, AtomicOrdering::Relaxed);
+                let df: polars::prelude::DataFrame = read_polars_file(&file, bytes, parquet_columns.clone(), deleted.as_ref(), &column_mapping, &table_schema, &expected)?;
+                let df: polars::prelude::DataFrame = apply_read_options(df, &options)?;
+                Ok(df.drop_many(&hidden))
+            });
+        let audited: AuditedStream<polars::prelude::DataFrame> = AuditedStream {
+            inner: stream.boxed(),
+            audit: Some(audit),
+            audit_logger: self.audit_logger.clone(),
+            bytes_read,
+            error: None,
+            rows: |df| df.height(),
+        };
+        Ok(audited.boxed())
+    }
+
+    /// If the user has permission to read the table, then this function returns a stream of arrow record batches.
+    /// At most `max_in_flight` files are downloaded ahead of the consumer, so memory stays bounded however large the table is.
+    /// The read is audited when the stream ends or is dropped, with the rows and bytes the consumer received.
+    ///
+    /// # Arguments
+    ///
+    /// * `table_name` - The fully qualified table name
+    /// * `options` - The version, columns and filters to read
+    /// * `max_in_flight` - The number of files downloaded concurrently
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let mut stream = reader.stream_delta_table_as_record_batches("my_catalog.my_schema.my_table", &ReadOptions::new(), 4).await?;
+    /// while let Some(batch) = stream.next().await {
+    ///     let batch: RecordBatch = batch?;
+    /// }
+    /// ```
+    pub async fn stream_delta_table_as_record_batches(&self, table_name: &str, options: &ReadOptions, max_in_flight: usize) -> Result<BoxStream<'static, Result<RecordBatch, DeltaTableError>>, DeltaTableError> {
+        let mut audit: AuditRecord = AuditRecord::start(&self.principal, table_name, "stream_delta_table_as_record_batches");
+        let prepared: Result<PreparedRead, DeltaTableError> = self.prepare_read(table_name, options, &mut audit).await;
+        if prepared.is_err() {
+            audit.finish(&prepared);
+            self.record_audit(&audit).await;
+        }
+        let prepared: PreparedRead = prepared?;
+
+        let options: ReadOptions = options.clone();
+        let layout: FileLayout = FileLayout {
+            table_schema: prepared.table_schema.clone(),
+            partition_columns: prepared.partition_columns.clone(),
+            column_mapping: prepared.column_mapping.clone(),
+            hidden: prepared.hidden.clone(),
+        };
+        let bytes_read: Arc<AtomicI64> = Arc::new(AtomicI64::new(0));
+        let downloaded_bytes: Arc<AtomicI64> = Arc::clone(&bytes_read);
+        let stream = prepared.download(max_in_flight)
+            .map(move |downloaded| {
+                let (file, bytes, deleted) = downloaded?;
+                downloaded_bytes.fetch_add(bytes.len() as i64, AtomicOrdering::Relaxed);
+                file_record_batches(&file, bytes, deleted.as_ref(), &layout, &options)
+            })
+            // each file is decoded into batches before the next file is taken from the download buffer
+            .flat_map(|batches| match batches {
+                Ok(batches) => futures::stream::iter(batches.into_iter().map(Ok).collect::<Vec<_>>()),
+                Err(e) => futures::stream::iter(vec![Err(e)]),
+            });
+        let audited: AuditedStream<RecordBatch> = AuditedStream {
+            inner: stream.boxed(),
+            audit: Some(audit),
+            audit_logger: self.audit_logger.clone(),
+            bytes_read,
+            error: None,
+            rows: |batch| batch.num_rows(),
+        };
+        Ok(audited.boxed())
+    }
+
+    // checks permissions and policies before touching storage, then loads the requested table version
+    // every read goes through here so authorization and audit behave the same on each read path
+    async fn open_authorized(&self, table_name: &str, options: &ReadOptions, path: ReadPath, audit: &mut AuditRecord) -> Result<(Table, deltalake::DeltaTable), DeltaTableError> {
+        let uc_table: Table = self.metastore_client.get_table(table_name).await
+            .map_err(|e| DeltaTableError::Generic(format!("Failed to get table {}: {}", table_name, e)))?;
+        let table_path = uc_table.storage_location.clone().unwrap_or_default();
+
+        let allowed: bool = self.permissions_client.can_read(table_name, &self.principal).await
+            .map_err(|e| DeltaTableError::Generic(format!("Failed to check permissions on {}: {}", table_name, e)))?;
+        if !allowed {
+            log::error!("Permissions of Object {} Denied.", table_name);
+            audit.deny("principal does not have read access");
+            return Err(DeltaTableError::Generic(String::from("Permission Denied.")));
+        }
+        if uc_table.has_access_policies() {
+            let refused: Option<String> = match path {
+                ReadPath::Files => Some(format!("Table {} is protected by a row filter or column mask, use read_delta_table_as_datafusion.", table_name)),
+                ReadPath::Datafusion if self.policy_enforcement == PolicyEnforcement::Refuse => Some(format!("Table {} is protected by a row filter or column mask.", table_name)),
+                _ => None,
+            };
+            if let Some(message) = refused {
+                log::error!("Object {} is protected by a row filter or column mask.", table_name);
+                audit.deny("table is protected by a row filter or column mask");
+                return Err(DeltaTableError::Generic(message));
+            }
+        }
+        log::info!("Validated Permissions on Object: {}", table_name);
+
+        log::info!("Reading Table: {}", table_path);
+        let storage_options: HashMap<String, String> = self.storage_options(&uc_table, "READ").await?;
+        let table: deltalake::DeltaTable = self.open_table(&table_path, storage_options, options).await?;
+        audit.table_version = Some(table.version());
+        if path == ReadPath::Files {
+            check_reader_features(&table)?;
+        }
+        Ok((uc_table, table))
+    }
+
+    // checks permissions and policies, then resolves the files of the requested table version
+    async fn prepare_read(&self, table_name: &str, options: &ReadOptions, audit: &mut AuditRecord) -> Result<PreparedRead, DeltaTableError> {
+        let (_, table): (Table, deltalake::DeltaTable) = self.open_authorized(table_name, options, ReadPath::Files, audit).await?;
+        // checked before pruning, file stats of hidden columns would leak their values too
+        let hidden: Vec<String> = self.authorize_columns(table_name, options, audit).await?;
+        let column_mapping: ColumnMapping = ColumnMapping::from_table(&table)?;
+        let partition_columns: Vec<String> = table.metadata()?.partition_columns.clone();
+        let files: Vec<DeltaFile> = prune_files(table_files(&table, &column_mapping)?, &options.filters, &partition_columns);
+
+        Ok(PreparedRead {
+            object_store: table.object_store(),
+            table_uri: table.table_uri(),
+            table_schema: table.get_schema()?.clone(),
+            column_mapping,
+            partition_columns,
+            files,
+            hidden,
+        })
+    }
+
+    /// If the user has permission to read the table, then this function returns a polars dataframe. 
+    ///
+    /// # Arguments
+    ///
+    /// * `table_name` - The fully qualified table name
+    /// * `parallel_read` - true/false argument to read the table serially or in parallel
+    /// * `options` - The version or timestamp to read, the latest version by default
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let table_name: &str = "my_catalog.my_schema.my_table";
+    /// let df = reader.read_delta_table_as_polars(table_path, true, &ReadOptions::new()).await.unwrap();
+    /// // the table as it was at the start of the year
+    /// let df = reader.read_delta_table_as_polars(table_path, true, &ReadOptions::new().with_timestamp("2024-01-01T00:00:00Z")).await.unwrap();
+    /// ```
+    pub async fn read_delta_table_as_polars(&self, table_name: &str, parallel_read: bool, options: &ReadOptions) -> Result<polars::prelude::DataFrame, DeltaTableError> {
+        let mut audit: AuditRecord = AuditRecord::start(&self.principal, table_name, "read_delta_table_as_polars");
+        let result = self.polars_read(table_name, parallel_read, options, &mut audit).await;
+        if let Ok(df) = &result {
+            audit.rows_returned = Some(df.height() as i64);
+        }
+        audit.finish(&result);
+        self.record_audit(&audit).await;
+        result
+    }
+
+    async fn polars_read(&self, table_name: &str, parallel_read: bool, options: &ReadOptions, audit: &mut AuditRecord) -> Result<polars::prelude::DataFrame, DeltaTableError> { //Result<polars::prelude::DataFrame, DeltaTableError> {        
+        // create empty DF - we will replace it later with the if/else
+        let mut df: polars::prelude::DataFrame = polars::prelude::DataFrame::default();
+
+        // polars reads the raw parquet files so policies can only be applied through datafusion
+        let (_, table): (Table, deltalake::DeltaTable) = self.open_authorized(table_name, options, ReadPath::Files, audit).await?;
+        let hidden: Vec<String> = self.authorize_columns(table_name, options, audit).await?;
+        let column_mapping: ColumnMapping = ColumnMapping::from_table(&table)?;
+        let schema: &StructType = table.get_schema()?;
+        let partition_columns: Vec<String> = table.metadata()?.partition_columns.clone();
+        let files: Vec<DeltaFile> = prune_files(table_files(&table, &column_mapping)?, &options.filters, &partition_columns);
+        let parquet_columns: Option<Vec<String>> = parquet_projection(options, &partition_columns, &column_mapping);
+        let expected: Schema = expected_schema(schema, read_columns(options).as_deref());
+        // get the table as a vector of bytes each index is a parquet file 
+        let table_bytes: Vec<Bytes> = if parallel_read {
+            log::info!("Parallel reading table.");
+            self.parallel_read_table_as_bytes(&table, &files).await?
+        } else {
+            log::info!("Seirially readin table.");
+            self.read_table_as_bytes(&table, &files).await?
+        };
+        
+        audit.bytes_read = Some(table_bytes.iter().map(|b| b.len() as i64).sum());
+
+        // load the bytes into a polars dataframe
+        let object_store: Arc<dyn ObjectStore> = table.object_store();
+        for (file, b) in files.iter().zip(table_bytes) {
+            let deleted: Option<RoaringTreemap> = match &file.deletion_vector {
+                Some(dv) => Some(load_deleted_rows(&object_store, &table.table_uri(), dv).await?),
+                None => None,
+            };
+            let new_df: polars::prelude::DataFrame = read_polars_file(file, b, parquet_columns.clone(), deleted.as_ref(), &column_mapping, schema, &expected)?;
+
+            if df.width() == 0 {
+                df = new_df;
             } else {
-                log::info!("Seirially readin table.");
-                table_bytes = self.read_table_as_bytes(&table_path).await?;
+                // every file is aligned to the table schema, so a failure here is an error rather than a skipped file
+                df.vstack_mut(&new_df)
+                    .map_err(|e| DeltaTableError::Generic(format!("Error stacking file {}: {}", file.path, e)))?;
             }
-            
-            // foreach file we need only the file name 
-            // load the bytes into a polars dataframe
-            for b in table_bytes {
-                let cursor = Cursor::new(b);
-                let new_df: polars::prelude::DataFrame = ParquetReader::new(cursor).finish().unwrap();
-
-                if df.is_empty() {
-                    df = new_df.clone();
-                } else {
-                    df = match df.vstack(&new_df) {
-                        Ok(stacked_df) => stacked_df,
-                        Err(e) => {
-                            // Handle the error if the vertical stack operation fails
-                            log::error!("Error stacking DataFrames: {}", e);
-                            df // Return the original DataFrame if the operation fails
-                        }
-                    };
+        }   
+
+        // files were only pruned, the filters still apply to the rows of the files that were read
+        if df.width() > 0 {
+            df = apply_read_options(df, options)?;
+        }
+
+        // drop columns hidden from the principal by local column policies
+        df = df.drop_many(&hidden);
+        Ok(df)
+    }
+
+    /// If the user has permission to read the table, returns the rows inserted, updated and deleted between two versions or timestamps as a polars dataframe.
+    /// Each row has a `_change_type` of insert, update_preimage, update_postimage or delete, a `_commit_version` and a `_commit_timestamp`.
+    ///
+    /// # Arguments
+    ///
+    /// * `table_name` - The fully qualified table name, `delta.enableChangeDataFeed` must be set on the table
+    /// * `options` - The first and last versions or timestamps of the feed
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let changes = reader.read_change_feed_as_polars("my_catalog.my_schema.my_table", &ChangeFeedOptions::from_version(5)).await?;
+    /// ```
+    pub async fn read_change_feed_as_polars(&self, table_name: &str, options: &ChangeFeedOptions) -> Result<polars::prelude::DataFrame, DeltaTableError> {
+        let mut audit: AuditRecord = AuditRecord::start(&self.principal, table_name, "read_change_feed_as_polars");
+        let result = self.change_feed(table_name, options, &mut audit).await;
+        if let Ok(df) = &result {
+            audit.rows_returned = Some(df.height() as i64);
+        }
+        audit.finish(&result);
+        self.record_audit(&audit).await;
+        result
+    }
+
+    /// If the user has permission to read the table, returns its change data feed as a datafusion dataframe.
+    ///
+    /// # Arguments
+    ///
+    /// * `table_name` - The fully qualified table name, `delta.enableChangeDataFeed` must be set on the table
+    /// * `options` - The first and last versions or timestamps of the feed
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let changes = reader.read_change_feed_as_datafusion("my_catalog.my_schema.my_table", &ChangeFeedOptions::from_timestamp("2024-01-01T00:00:00Z")).await?;
+    /// ```
+    pub async fn read_change_feed_as_datafusion(&self, table_name: &str, options: &ChangeFeedOptions) -> Result<deltalake::datafusion::prelude::DataFrame, DeltaTableError> {
+        let mut audit: AuditRecord = AuditRecord::start(&self.principal, table_name, "read_change_feed_as_datafusion");
+        let result = match self.change_feed(table_name, options, &mut audit).await {
+            Ok(mut df) => {
+                audit.rows_returned = Some(df.height() as i64);
+                polars_to_record_batches(&mut df).and_then(|batches| {
+                    let ctx: SessionContext = SessionContext::new();
+                    if batches.is_empty() {
+                        let schema: ArrowSchema = cdf_arrow_schema(&df)?;
+                        return Ok(ctx.read_batch(RecordBatch::new_empty(Arc::new(schema)))?);
+                    }
+                    Ok(ctx.read_batches(batches)?)
+                })
+            }
+            Err(e) => Err(e),
+        };
+        audit.finish(&result);
+        self.record_audit(&audit).await;
+        result
+    }
+
+    // reads the commits of the feed in order, from their change files when the writer produced them
+    // otherwise every row of an added file is an insert and every row of a removed file a delete
+    async fn change_feed(&self, table_name: &str, options: &ChangeFeedOptions, audit: &mut AuditRecord) -> Result<polars::prelude::DataFrame, DeltaTableError> {
+        if options.starting_version.is_some() && options.starting_timestamp.is_some() {
+            return Err(DeltaTableError::Generic(String::from("Only one of starting version or timestamp can be set.")));
+        }
+        if options.ending_version.is_some() && options.ending_timestamp.is_some() {
+            return Err(DeltaTableError::Generic(String::from("Only one of ending version or timestamp can be set.")));
+        }
+        let (_, mut table): (Table, deltalake::DeltaTable) = self.open_authorized(table_name, &options.ending_read_options(), ReadPath::Files, audit).await?;
+
+        // timestamps are resolved with the same clock as the _commit_timestamp of the rows, not by delta-rs time travel
+        let clock: CommitClock = CommitClock::from_table(&table)?;
+        let commits: Vec<(i64, i64)> = if options.starting_timestamp.is_some() || options.ending_timestamp.is_some() {
+            commit_timestamps(&table, &clock).await?
+        } else {
+            Vec::new()
+        };
+        if let Some(timestamp) = &options.ending_timestamp {
+            let millis: i64 = timestamp_millis(timestamp)?;
+            let version: i64 = commits.iter().rev().find(|(_, ts)| *ts <= millis).map(|(version, _)| *version)
+                .ok_or_else(|| DeltaTableError::Generic(format!("Table {} has no commit at or before {}.", table_name, timestamp)))?;
+            table.load_version(version).await?;
+            audit.table_version = Some(version);
+        }
+        let enabled: Option<String> = table.metadata()?.configuration.get(CHANGE_DATA_FEED).cloned().flatten();
+        if enabled.as_deref() != Some("true") {
+            return Err(DeltaTableError::Generic(format!("Table {} does not have {} enabled.", table_name, CHANGE_DATA_FEED)));
+        }
+        let column_mapping: ColumnMapping = ColumnMapping::from_table(&table)?;
+        let table_schema: StructType = table.get_schema()?.clone();
+        let object_store: Arc<dyn ObjectStore> = table.object_store();
+        let table_uri: String = table.table_uri();
+
+        let ending_version: i64 = table.version();
+        let starting_version: i64 = match &options.starting_timestamp {
+            Some(timestamp) => {
+                let millis: i64 = timestamp_millis(timestamp)?;
+                commits.iter().find(|(_, ts)| *ts >= millis).map(|(version, _)| *version)
+                    .ok_or_else(|| DeltaTableError::Generic(format!("Table {} has no commit at or after {}.", table_name, timestamp)))?
+            }
+            None => options.starting_version.unwrap_or(0),
+        };
+        if starting_version > ending_version {
+            return Err(DeltaTableError::Generic(format!("The feed starts at version {} after it ends at version {}.", starting_version, ending_version)));
+        }
+        log::info!("Reading changes of {} from version {} to {}", table_name, starting_version, ending_version);
 
+        let mut expected: Schema = expected_schema(&table_schema, None);
+        expected.with_column(CHANGE_TYPE.into(), polars::prelude::DataType::String);
+        let mut df: Option<polars::prelude::DataFrame> = None;
+        let mut bytes_read: i64 = 0;
+        for version in starting_version..=ending_version {
+            let commit: ChangeCommit = read_change_commit(&object_store, version, &column_mapping, &clock).await?;
+            for (file, change_type) in commit.files {
+                log::info!("Loading {} changes from file: {}", change_type.unwrap_or("recorded"), file.path);
+                let bytes: Bytes = object_store.get(&file.path).await?.bytes().await?;
+                bytes_read += bytes.len() as i64;
+                let deleted: Option<RoaringTreemap> = match &file.deletion_vector {
+                    Some(dv) => Some(load_deleted_rows(&object_store, &table_uri, dv).await?),
+                    None => None,
+                };
+                let mut file_df: polars::prelude::DataFrame = read_polars_file(&file, bytes, None, deleted.as_ref(), &column_mapping, &table_schema, &expected)?;
+                let height: usize = file_df.height();
+                let mut change_columns: Vec<Series> = vec![
+                    Series::new(COMMIT_VERSION, vec![version; height]),
+                    Series::new(COMMIT_TIMESTAMP, vec![commit.timestamp; height])
+                        .cast(&polars::prelude::DataType::Datetime(TimeUnit::Milliseconds, None))
+                        .map_err(|e| DeltaTableError::Generic(e.to_string()))?,
+                ];
+                // change files carry their own change type
+                if let Some(change_type) = change_type {
+                    change_columns.push(Series::new(CHANGE_TYPE, vec![change_type; height]));
+                }
+                for column in change_columns {
+                    file_df.with_column(column).map_err(|e| DeltaTableError::Generic(e.to_string()))?;
                 }
-            }   
-        }        
+                df = match df {
+                    Some(mut stacked) => {
+                        stacked.vstack_mut(&file_df)
+                            .map_err(|e| DeltaTableError::Generic(format!("Error stacking file {}: {}", file.path, e)))?;
+                        Some(stacked)
+                    }
+                    None => Some(file_df),
+                };
+            }
+        }
+        audit.bytes_read = Some(bytes_read);
+
+        let mut df: polars::prelude::DataFrame = match df {
+            Some(df) => df,
+            None => {
+                let mut schema: Schema = expected.clone();
+                schema.with_column(COMMIT_VERSION.into(), polars::prelude::DataType::Int64);
+                schema.with_column(COMMIT_TIMESTAMP.into(), polars::prelude::DataType::Datetime(TimeUnit::Milliseconds, None));
+                polars::prelude::DataFrame::from(&schema)
+            }
+        };
+
+        // drop columns hidden from the principal by local column policies
+        let hidden: Vec<String> = self.hidden_columns(table_name).await?;
+        if !hidden.is_empty() {
+            audit.hidden_columns = Some(hidden.join(","));
+            df = df.drop_many(&hidden);
+        }
         Ok(df)
     }
 
+    /// If the user has permission to read the table, returns its commits newest first and stores them in the table_history table.
+    ///
+    /// # Arguments
+    ///
+    /// * `table_name` - The fully qualified table name
+    /// * `limit` - The number of commits to return, every commit still in the log when None
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let history: Vec<TableCommit> = reader.table_history("my_catalog.my_schema.my_table", Some(20)).await?;
+    /// ```
+    pub async fn table_history(&self, table_name: &str, limit: Option<usize>) -> Result<Vec<TableCommit>, DeltaTableError> {
+        let mut audit: AuditRecord = AuditRecord::start(&self.principal, table_name, "table_history");
+        let result: Result<Vec<TableCommit>, DeltaTableError> = match self.open_authorized(table_name, &ReadOptions::new(), ReadPath::Metadata, &mut audit).await {
+            Ok((uc_table, table)) => match commit_history(&table, limit).await {
+                Ok(history) => {
+                    self.metastore_client.sql_client.write_table_history(self.metastore_client.workspace_name(), &uc_table, &history).await
+                        .map_err(|e| DeltaTableError::Generic(format!("Failed to store history of {}: {}", table_name, e)))
+                        .map(|_| history)
+                }
+                Err(e) => Err(e),
+            },
+            Err(e) => Err(e),
+        };
+        audit.finish(&result);
+        self.record_audit(&audit).await;
+        result
+    }
+
+    /// If the user has permission to read the table, returns the file count, size, partition columns and protocol of its latest version
+    /// and stores them in the table_details table next to the table's row.
+    ///
+    /// # Arguments
+    ///
+    /// * `table_name` - The fully qualified table name
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let details: TableDetails = reader.table_details("my_catalog.my_schema.my_table").await?;
+    /// log::info!("{} files, {} bytes", details.num_files, details.size_in_bytes);
+    /// ```
+    pub async fn table_details(&self, table_name: &str) -> Result<TableDetails, DeltaTableError> {
+        let mut audit: AuditRecord = AuditRecord::start(&self.principal, table_name, "table_details");
+        let result: Result<TableDetails, DeltaTableError> = match self.open_authorized(table_name, &ReadOptions::new(), ReadPath::Metadata, &mut audit).await {
+            Ok((uc_table, table)) => match table_details(table_name, &table).await {
+                Ok(details) => {
+                    self.metastore_client.sql_client.write_table_details(self.metastore_client.workspace_name(), &uc_table, &details).await
+                        .map_err(|e| DeltaTableError::Generic(format!("Failed to store details of {}: {}", table_name, e)))
+                        .map(|_| details)
+                }
+                Err(e) => Err(e),
+            },
+            Err(e) => Err(e),
+        };
+        audit.finish(&result);
+        self.record_audit(&audit).await;
+        result
+    }
+
+    // the hidden columns of the table, refusing reads whose selected columns or filters use them
+    // a filter on a hidden column would reveal its values through the rows that match
+    async fn authorize_columns(&self, table_name: &str, options: &ReadOptions, audit: &mut AuditRecord) -> Result<Vec<String>, DeltaTableError> {
+        let hidden: Vec<String> = self.hidden_columns(table_name).await?;
+        if hidden.is_empty() {
+            return Ok(hidden);
+        }
+        audit.hidden_columns = Some(hidden.join(","));
+        let used: Vec<String> = hidden_columns_used(options, &hidden);
+        if !used.is_empty() {
+            log::error!("Read of {} uses columns {:?} hidden from {}", table_name, used, self.principal);
+            audit.deny("read selects or filters on hidden columns");
+            return Err(DeltaTableError::Generic(format!("Permission Denied. Columns {} of {} are hidden.", used.join(", "), table_name)));
+        }
+        Ok(hidden)
+    }
+
+    // columns of the table hidden from the principal or any of their groups, an audit record is written when any are hidden
+    async fn hidden_columns(&self, table_name: &str) -> Result<Vec<String>, DeltaTableError> {
+        let sql_client = &self.metastore_client.sql_client;
+        let hidden: Vec<String> = principal_hidden_columns(&self.permissions_client, sql_client, self.metastore_client.workspace_name(), &self.principal, table_name).await?;
+
+        if !hidden.is_empty() {
+            log::info!("Hiding columns {:?} of {} from {}", hidden, table_name, self.principal);
+            sql_client.write_column_policy_audit(self.metastore_client.workspace_name(), &self.principal, table_name, &hidden).await
+                .map_err(|e| DeltaTableError::Generic(format!("Failed to write column policy audit: {}", e)))?;
+        }
+        Ok(hidden)
+    }
+
 
     pub async fn print_datafusion_dataframe(&self, df: deltalake::datafusion::prelude::DataFrame) {
         let data = df.collect().await.unwrap();
@@ -231,8 +1478,1398 @@ impl DeltaLakeReader {
 
 }
 
-// pub struct Writer {
-//     placeholder: String,
-// }
+// columns of the table hidden from the principal or any of their groups by local column policies
+async fn principal_hidden_columns(permissions_client: &Permissions, sql_client: &crate::sql::sql_client::SqlClient, workspace_name: &str, principal: &str, table_name: &str) -> Result<Vec<String>, DeltaTableError> {
+    let mut principals: Vec<String> = permissions_client.get_principal_groups(principal).await
+        .map_err(|e| DeltaTableError::Generic(format!("Failed to get groups of {}: {}", principal, e)))?;
+    principals.push(principal.to_string());
+
+    sql_client.hidden_columns(workspace_name, table_name, &principals).await
+        .map_err(|e| DeltaTableError::Generic(format!("Failed to get column policies of {}: {}", table_name, e)))
+}
+
+// storage options for the reader's or writer's own credentials, or credentials vended by unity catalog for the operation
+async fn table_storage_options(storage_credentials: &StorageOptions, permissions_client: &Permissions, credential_vending: bool, credential_cache: &Mutex<HashMap<String, TemporaryTableCredentials>>, uc_table: &Table, operation: &str) -> Result<HashMap<String, String>, DeltaTableError> {
+    let table_path: String = uc_table.storage_location.clone().unwrap_or_default();
+    if !credential_vending {
+        return storage_credentials.for_location(&table_path).map_err(DeltaTableError::Generic);
+    }
+    let cache_key: String = format!("{}:{}", uc_table.table_id, operation);
+
+    let mut cache = credential_cache.lock().await;
+    let cached: Option<TemporaryTableCredentials> = cache.get(&cache_key).filter(|c| !c.expires_within(CREDENTIAL_REFRESH_BUFFER_MS)).cloned();
+    let credentials: TemporaryTableCredentials = match cached {
+        Some(c) => c,
+        None => {
+            let c: TemporaryTableCredentials = permissions_client.generate_temporary_table_credentials(&uc_table.table_id, operation).await
+                .map_err(|e| DeltaTableError::Generic(format!("Failed to get temporary credentials for {}: {}", uc_table.full_name, e)))?;
+            cache.insert(cache_key, c.clone());
+            c
+        }
+    };
+    let mut options: HashMap<String, String> = credentials.to_hash_map(&table_path).map_err(DeltaTableError::Generic)?;
+    // vended aws credentials do not say where the bucket is
+    if StorageScheme::from_location(&table_path) == Ok(StorageScheme::S3) {
+        if let Some(s3) = &storage_credentials.s3 {
+            options.extend(s3.location_options());
+        }
+    }
+    Ok(options)
+}
+
+// the commits of the loaded table version, newest first
+// versions come from the commit file names, log cleanup leaves gaps so they cannot be counted back from the table version
+async fn commit_history(table: &deltalake::DeltaTable, limit: Option<usize>) -> Result<Vec<TableCommit>, DeltaTableError> {
+    let object_store: Arc<dyn ObjectStore> = table.object_store();
+    let clock: CommitClock = CommitClock::from_table(table)?;
+    let mut commits: Vec<(i64, i64)> = log_commits(table).await?;
+    commits.reverse();
+    if let Some(limit) = limit {
+        commits.truncate(limit);
+    }
+
+    let mut history: Vec<TableCommit> = Vec::with_capacity(commits.len());
+    for (version, last_modified) in commits {
+        let commit: CommitInfo = read_commit_info(&object_store, version).await?.unwrap_or_default();
+        let commit_timestamp: i64 = clock.timestamp(version, Some(&commit), last_modified);
+        let operation_parameters: Option<String> = match &commit.operation_parameters {
+            Some(parameters) => Some(serde_json::to_string(parameters)
+                .map_err(|e| DeltaTableError::Generic(format!("Invalid operation parameters: {}", e)))?),
+            None => None,
+        };
+        history.push(TableCommit {
+            version,
+            commit_timestamp: Some(commit_timestamp),
+            operation: commit.operation,
+            operation_parameters,
+            user_name: commit.user_name.or(commit.user_id),
+        });
+    }
+    Ok(history)
+}
+
+// the commits of the loaded table version in order, with the modification time of their files in epoch milliseconds
+async fn log_commits(table: &deltalake::DeltaTable) -> Result<Vec<(i64, i64)>, DeltaTableError> {
+    let object_store: Arc<dyn ObjectStore> = table.object_store();
+    let mut commits: Vec<(i64, i64)> = Vec::new();
+    let mut listing = object_store.list(Some(&Path::from("_delta_log")));
+    while let Some(meta) = listing.next().await {
+        let meta = meta?;
+        if let Some(version) = commit_version(&meta.location) {
+            if version <= table.version() {
+                commits.push((version, meta.last_modified.timestamp_millis()));
+            }
+        }
+    }
+    commits.sort_unstable();
+    Ok(commits)
+}
+
+// the commits of the loaded table version in order, with their timestamps by the clock
+// commit files are only read for the in-commit timestamp, the listing has the modification times
+async fn commit_timestamps(table: &deltalake::DeltaTable, clock: &CommitClock) -> Result<Vec<(i64, i64)>, DeltaTableError> {
+    let object_store: Arc<dyn ObjectStore> = table.object_store();
+    let mut timestamps: Vec<(i64, i64)> = Vec::new();
+    for (version, last_modified) in log_commits(table).await? {
+        let timestamp: i64 = if clock.uses_in_commit(version) {
+            let info: Option<CommitInfo> = read_commit_info(&object_store, version).await?;
+            clock.timestamp(version, info.as_ref(), last_modified)
+        } else {
+            last_modified
+        };
+        timestamps.push((version, timestamp));
+    }
+    Ok(timestamps)
+}
+
+// an RFC 3339 timestamp in epoch milliseconds
+fn timestamp_millis(timestamp: &str) -> Result<i64, DeltaTableError> {
+    Ok(chrono::DateTime::parse_from_rfc3339(timestamp)
+        .map_err(|e| DeltaTableError::Generic(format!("Invalid timestamp {}: {}", timestamp, e)))?
+        .timestamp_millis())
+}
+
+// the version of a commit file, i.e. _delta_log/00000000000000000010.json, None for checkpoints and other log files
+fn commit_version(path: &Path) -> Option<i64> {
+    let digits: &str = path.filename()?.strip_suffix(".json")?;
+    if digits.len() != 20 || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    digits.parse().ok()
+}
+
+// the commit info action of a commit, None when the writer did not record one
+async fn read_commit_info(object_store: &Arc<dyn ObjectStore>, version: i64) -> Result<Option<CommitInfo>, DeltaTableError> {
+    let commit_path: Path = Path::from(format!("_delta_log/{:020}.json", version));
+    let bytes: Bytes = object_store.get(&commit_path).await?.bytes().await?;
+    for line in bytes.split(|b| *b == b'\n').filter(|line| !line.is_empty()) {
+        let action: Action = serde_json::from_slice(line)
+            .map_err(|e| DeltaTableError::Generic(format!("Invalid action in commit {}: {}", version, e)))?;
+        if let Action::CommitInfo(info) = action {
+            return Ok(Some(info));
+        }
+    }
+    Ok(None)
+}
+
+async fn table_details(table_name: &str, table: &deltalake::DeltaTable) -> Result<TableDetails, DeltaTableError> {
+    let files = table.snapshot()?.file_actions()?;
+    let protocol = table.protocol()?;
+    let features = |mut names: Vec<String>| -> Option<String> {
+        names.sort();
+        if names.is_empty() { None } else { Some(names.join(",")) }
+    };
+    let last_commit_timestamp: Option<i64> = commit_history(table, Some(1)).await?.first().and_then(|c| c.commit_timestamp);
+
+    Ok(TableDetails {
+        table_name: table_name.to_string(),
+        table_version: table.version(),
+        last_commit_timestamp,
+        num_files: files.len() as i64,
+        size_in_bytes: files.iter().map(|add| add.size).sum(),
+        partition_columns: table.metadata()?.partition_columns.join(","),
+        min_reader_version: protocol.min_reader_version,
+        min_writer_version: protocol.min_writer_version,
+        reader_features: features(protocol.reader_features.iter().flatten().map(|f| format!("{:?}", f)).collect()),
+        writer_features: features(protocol.writer_features.iter().flatten().map(|f| format!("{:?}", f)).collect()),
+    })
+}
+
+// which clock commit timestamps come from, the in-commit timestamp from the version the table feature was enabled at
+// and the modification time of the commit file before that, the timestamp field of commit info is never used
+// https://github.com/delta-io/delta/blob/master/PROTOCOL.md#in-commit-timestamps
+struct CommitClock {
+    in_commit_since: Option<i64>, // None when in-commit timestamps are not enabled
+}
+impl CommitClock {
+    fn from_table(table: &deltalake::DeltaTable) -> Result<Self, DeltaTableError> {
+        let configuration = &table.metadata()?.configuration;
+        let enabled: Option<String> = configuration.get(IN_COMMIT_TIMESTAMPS).cloned().flatten();
+        if enabled.as_deref() != Some("true") {
+            return Ok(CommitClock { in_commit_since: None });
+        }
+        // tables created with the feature enabled do not record an enablement version
+        let since: i64 = configuration.get(IN_COMMIT_TIMESTAMP_VERSION).cloned().flatten()
+            .and_then(|version| version.parse().ok())
+            .unwrap_or(0);
+        Ok(CommitClock { in_commit_since: Some(since) })
+    }
+
+    fn uses_in_commit(&self, version: i64) -> bool {
+        self.in_commit_since.is_some_and(|since| version >= since)
+    }
+
+    // the timestamp of a commit in epoch milliseconds
+    fn timestamp(&self, version: i64, info: Option<&CommitInfo>, last_modified: i64) -> i64 {
+        if self.uses_in_commit(version) {
+            let in_commit: Option<i64> = info.and_then(|info| info.info.get("inCommitTimestamp")).and_then(|ts| ts.as_i64());
+            if let Some(ts) = in_commit {
+                return ts;
+            }
+        }
+        last_modified
+    }
+}
+
+// the data change files of a single commit, with the change type of files that are not change files
+struct ChangeCommit {
+    timestamp: i64, // epoch milliseconds
+    files: Vec<(DeltaFile, Option<&'static str>)>,
+}
+
+// reads a commit of the delta log, change files replace the commit's add and remove actions when there are any
+// https://github.com/delta-io/delta/blob/master/PROTOCOL.md#change-data-files
+async fn read_change_commit(object_store: &Arc<dyn ObjectStore>, version: i64, column_mapping: &ColumnMapping, clock: &CommitClock) -> Result<ChangeCommit, DeltaTableError> {
+    let commit_path: Path = Path::from(format!("_delta_log/{:020}.json", version));
+    let result = object_store.get(&commit_path).await
+        .map_err(|e| DeltaTableError::Generic(format!("Failed to load commit {}, it may have been cleaned up: {}", version, e)))?;
+    let last_modified: i64 = result.meta.last_modified.timestamp_millis();
+    let mut timestamp: i64 = last_modified;
+    let bytes: Bytes = result.bytes().await?;
+
+    let mut change_files: Vec<(DeltaFile, Option<&'static str>)> = Vec::new();
+    let mut data_files: Vec<(DeltaFile, Option<&'static str>)> = Vec::new();
+    for line in bytes.split(|b| *b == b'\n').filter(|line| !line.is_empty()) {
+        let action: Action = serde_json::from_slice(line)
+            .map_err(|e| DeltaTableError::Generic(format!("Invalid action in commit {}: {}", version, e)))?;
+        match action {
+            Action::CommitInfo(info) => timestamp = clock.timestamp(version, Some(&info), last_modified),
+            Action::Cdc(cdc) => change_files.push((change_file(&cdc.path, cdc.partition_values, None, column_mapping)?, None)),
+            Action::Add(add) if add.data_change => {
+                data_files.push((change_file(&add.path, add.partition_values, add.deletion_vector, column_mapping)?, Some("insert")));
+            }
+            Action::Remove(remove) if remove.data_change => {
+                let partition_values: HashMap<String, Option<String>> = remove.partition_values.unwrap_or_default();
+                data_files.push((change_file(&remove.path, partition_values, remove.deletion_vector, column_mapping)?, Some("delete")));
+            }
+            _ => {}
+        }
+    }
+    let files: Vec<(DeltaFile, Option<&'static str>)> = if change_files.is_empty() { data_files } else { change_files };
+    Ok(ChangeCommit { timestamp, files })
+}
+
+fn change_file(path: &str, partition_values: HashMap<String, Option<String>>, deletion_vector: Option<DeletionVectorDescriptor>, column_mapping: &ColumnMapping) -> Result<DeltaFile, DeltaTableError> {
+    let path: Path = Path::from_url_path(path)
+        .map_err(|e| DeltaTableError::Generic(format!("Invalid file path {}: {}", path, e)))?;
+    Ok(DeltaFile {
+        path,
+        partition_values: column_mapping.logical_keys(partition_values),
+        stats: None,
+        deletion_vector,
+    })
+}
+
+// the arrow schema of an empty change feed, the parquet round trip used for rows has no batches to take it from
+fn cdf_arrow_schema(df: &polars::prelude::DataFrame) -> Result<ArrowSchema, DeltaTableError> {
+    let mut empty: polars::prelude::DataFrame = df.clear();
+    let mut buffer: Vec<u8> = Vec::new();
+    ParquetWriter::new(&mut buffer).finish(&mut empty)
+        .map_err(|e| DeltaTableError::Generic(format!("Failed to convert dataframe: {}", e)))?;
+    let options: ArrowReaderOptions = ArrowReaderOptions::new().with_skip_arrow_metadata(true);
+    let builder: ParquetRecordBatchReaderBuilder<Bytes> = ParquetRecordBatchReaderBuilder::try_new_with_options(Bytes::from(buffer), options)?;
+    Ok(builder.schema().as_ref().clone())
+}
+
+// the data files of the loaded table version, add action paths are url encoded and relative to the table root
+// partition values and stats are keyed by physical name in the log and translated to logical names
+fn table_files(table: &deltalake::DeltaTable, column_mapping: &ColumnMapping) -> Result<Vec<DeltaFile>, DeltaTableError> {
+    let mut files: Vec<DeltaFile> = Vec::new();
+    for add in table.snapshot()?.file_actions()? {
+        let path: Path = Path::from_url_path(&add.path)
+            .map_err(|e| DeltaTableError::Generic(format!("Invalid file path {}: {}", add.path, e)))?;
+        let stats: Option<FileStats> = add.stats.as_deref().and_then(FileStats::from_json).map(|stats| FileStats {
+            num_records: stats.num_records,
+            min_values: column_mapping.logical_keys(stats.min_values),
+            max_values: column_mapping.logical_keys(stats.max_values),
+            null_count: column_mapping.logical_keys(stats.null_count),
+        });
+        files.push(DeltaFile {
+            path,
+            partition_values: column_mapping.logical_keys(add.partition_values.clone()),
+            stats,
+            deletion_vector: add.deletion_vector.clone(),
+        });
+    }
+    Ok(files)
+}
+
+// decodes one parquet file, drops its deleted rows, restores logical column names and adds its partition columns
+fn read_polars_file(file: &DeltaFile, bytes: Bytes, parquet_columns: Option<Vec<String>>, deleted: Option<&RoaringTreemap>, column_mapping: &ColumnMapping, table_schema: &StructType, expected: &Schema) -> Result<polars::prelude::DataFrame, DeltaTableError> {
+    let mut df: polars::prelude::DataFrame = ParquetReader::new(Cursor::new(bytes)).with_columns(parquet_columns).finish()
+        .map_err(|e| DeltaTableError::Generic(format!("Failed to read file {}: {}", file.path, e)))?;
+    // deletion vectors hold row indexes within the file, so they are applied before anything else
+    if let Some(deleted) = deleted {
+        let keep: BooleanChunked = BooleanChunked::from_iter_values("keep", (0..df.height() as u64).map(|i| !deleted.contains(i)));
+        df = df.filter(&keep)
+            .map_err(|e| DeltaTableError::Generic(format!("Failed to apply deletion vector of {}: {}", file.path, e)))?;
+    }
+    column_mapping.rename_polars(&mut df)?;
+    // partition values are not stored in the parquet files so they are added back from the log
+    add_partition_columns(&mut df, file, table_schema)?;
+    align_to_schema(df, expected)
+        .map_err(|e| DeltaTableError::Generic(format!("Failed to align file {} to the table schema: {}", file.path, e)))
+}
+
+// the hidden columns the read options select or filter on
+fn hidden_columns_used(options: &ReadOptions, hidden: &[String]) -> Vec<String> {
+    let mut used: Vec<String> = Vec::new();
+    let selected = options.columns.iter().flatten();
+    let filtered = options.filters.iter().map(|f| &f.column);
+    for column in selected.chain(filtered) {
+        if hidden.contains(column) && !used.contains(column) {
+            used.push(column.clone());
+        }
+    }
+    used
+}
+
+// the table schema limited to the columns being read, all columns when None
+fn expected_schema(table_schema: &StructType, columns: Option<&[String]>) -> Schema {
+    table_schema.fields().iter()
+        .filter(|f| columns.is_none_or(|c| c.contains(f.name())))
+        .map(|f| Field::new(f.name(), polars_type(f.data_type())))
+        .collect()
+}
+
+// the columns a read needs from each file, selected columns plus the columns filters refer to
+fn read_columns(options: &ReadOptions) -> Option<Vec<String>> {
+    let mut columns: Vec<String> = options.columns.clone()?;
+    for filter in &options.filters {
+        if !columns.contains(&filter.column) {
+            columns.push(filter.column.clone());
+        }
+    }
+    Some(columns)
+}
+
+// files written before a schema change are cast to the current types, columns added since are filled with nulls,
+// dropped columns are removed and the columns are put in table order
+fn align_to_schema(df: polars::prelude::DataFrame, expected: &Schema) -> PolarsResult<polars::prelude::DataFrame> {
+    let height: usize = df.height();
+    let mut columns: Vec<Series> = Vec::with_capacity(expected.len());
+    for (name, dtype) in expected.iter() {
+        let series: Series = match df.column(name) {
+            // strict so values that do not fit the new type fail the read instead of becoming nulls
+            Ok(column) if column.dtype() != dtype => column.strict_cast(dtype)?,
+            Ok(column) => column.clone(),
+            Err(_) => Series::full_null(name, height, dtype),
+        };
+        columns.push(series);
+    }
+    polars::prelude::DataFrame::new(columns)
+}
+
+// drops files that cannot hold a matching row, partition filters compare the file's partition value and other filters its min/max stats
+fn prune_files(files: Vec<DeltaFile>, filters: &[Filter], partition_columns: &[String]) -> Vec<DeltaFile> {
+    let total: usize = files.len();
+    let kept: Vec<DeltaFile> = files.into_iter()
+        .filter(|file| filters.iter().all(|filter| {
+            if partition_columns.contains(&filter.column) {
+                filter.matches_partition(file.partition_values.get(&filter.column).and_then(|v| v.as_deref()))
+            } else {
+                file.stats.as_ref().is_none_or(|stats| filter.may_match_stats(stats))
+            }
+        }))
+        .collect();
+    if !filters.is_empty() {
+        log::info!("Reading {} of {} files after pruning.", kept.len(), total);
+    }
+    kept
+}
+
+// the columns to read from each parquet file, selected and filtered columns that are not partition columns
+fn parquet_projection(options: &ReadOptions, partition_columns: &[String], column_mapping: &ColumnMapping) -> Option<Vec<String>> {
+    let mut columns: Vec<String> = read_columns(options)?;
+    columns.retain(|c| !partition_columns.contains(c));
+    // a file read with no columns has no rows, so read it whole when only partition columns are selected
+    if columns.is_empty() {
+        None
+    } else {
+        Some(columns.iter().map(|c| column_mapping.physical(c)).collect())
+    }
+}
 
+// applies the row filters and column selection to the stacked dataframe
+fn apply_read_options(df: polars::prelude::DataFrame, options: &ReadOptions) -> Result<polars::prelude::DataFrame, DeltaTableError> {
+    let to_error = |e: PolarsError| DeltaTableError::Generic(format!("Failed to apply read options: {}", e));
+    let mut df: polars::prelude::DataFrame = df;
+    if !options.filters.is_empty() {
+        let mut predicate: Option<polars::prelude::Expr> = None;
+        for filter in &options.filters {
+            let dtype: Option<polars::prelude::DataType> = df.column(&filter.column).ok().map(|c| c.dtype().clone());
+            let expr: polars::prelude::Expr = filter.to_expr(dtype.as_ref());
+            predicate = Some(match predicate {
+                Some(p) => p.and(expr),
+                None => expr,
+            });
+        }
+        df = df.lazy().filter(predicate.unwrap()).collect().map_err(to_error)?;
+    }
+    if let Some(columns) = &options.columns {
+        df = df.select(columns).map_err(to_error)?;
+    }
+    Ok(df)
+}
 
+// wraps a datafusion query with the column selection and row filters of the read options
+fn read_options_query(qry: String, options: &ReadOptions) -> String {
+    if options.columns.is_none() && options.filters.is_empty() {
+        return qry;
+    }
+    let select_list: String = match &options.columns {
+        Some(columns) => columns.iter().map(|c| format!("\"{}\"", c)).collect::<Vec<String>>().join(", "),
+        None => String::from("*"),
+    };
+    let mut wrapped: String = format!("SELECT {} FROM ({})", select_list, qry);
+    if !options.filters.is_empty() {
+        let conditions: Vec<String> = options.filters.iter().map(|f| f.to_sql()).collect();
+        wrapped.push_str(&format!(" WHERE {}", conditions.join(" AND ")));
+    }
+    wrapped
+}
+
+// the table layout every file of a streamed read is decoded against
+struct FileLayout {
+    table_schema: StructType,
+    partition_columns: Vec<String>,
+    column_mapping: ColumnMapping,
+    hidden: Vec<String>, // columns hidden from the principal by local column policies
+}
+
+// decodes one parquet file into record batches with partition columns, row filters and column selection applied
+fn file_record_batches(file: &DeltaFile, bytes: Bytes, deleted: Option<&RoaringTreemap>, layout: &FileLayout, options: &ReadOptions) -> Result<Vec<RecordBatch>, DeltaTableError> {
+    let FileLayout { table_schema, partition_columns, column_mapping, hidden } = layout;
+    let mut builder: ParquetRecordBatchReaderBuilder<Bytes> = ParquetRecordBatchReaderBuilder::try_new(bytes)?;
+    if let Some(columns) = parquet_projection(options, partition_columns, column_mapping) {
+        let file_schema = builder.schema().clone();
+        let roots: Vec<usize> = columns.iter().filter_map(|c| file_schema.index_of(c).ok()).collect();
+        let mask: ProjectionMask = ProjectionMask::roots(builder.parquet_schema(), roots);
+        builder = builder.with_projection(mask);
+    }
+
+    let mut batches: Vec<RecordBatch> = Vec::new();
+    let mut row_offset: u64 = 0; // index of the batch's first row within the file
+    for batch in builder.with_batch_size(RECORD_BATCH_SIZE).build()? {
+        let mut batch: RecordBatch = batch?;
+        let rows: u64 = batch.num_rows() as u64;
+        if let Some(deleted) = deleted {
+            let keep: BooleanArray = BooleanArray::from((row_offset..row_offset + rows).map(|i| !deleted.contains(i)).collect::<Vec<bool>>());
+            batch = filter_record_batch(&batch, &keep)?;
+        }
+        row_offset += rows;
+        let batch: RecordBatch = column_mapping.rename_batch(batch)?;
+        let batch: RecordBatch = add_partition_arrays(batch, file, table_schema)?;
+        let batch: RecordBatch = align_batch(batch, table_schema, read_columns(options).as_deref())?;
+        let batch: RecordBatch = filter_batch(batch, &options.filters)?;
+
+        // select the requested columns in order, or every column that is not hidden
+        let schema = batch.schema();
+        let names: Vec<String> = match &options.columns {
+            Some(columns) => columns.clone(),
+            None => schema.fields().iter().map(|f| f.name().clone()).collect(),
+        };
+        let mut indices: Vec<usize> = Vec::new();
+        for name in names.iter().filter(|n| !hidden.contains(n)) {
+            indices.push(schema.index_of(name)?);
+        }
+        batches.push(batch.project(&indices)?);
+    }
+    Ok(batches)
+}
+
+// the arrow counterpart of add_partition_columns
+fn add_partition_arrays(batch: RecordBatch, file: &DeltaFile, table_schema: &StructType) -> Result<RecordBatch, DeltaTableError> {
+    let rows: usize = batch.num_rows();
+    let mut fields: Vec<ArrowField> = batch.schema().fields().iter().map(|f| f.as_ref().clone()).collect();
+    let mut columns: Vec<ArrayRef> = batch.columns().to_vec();
+
+    let mut names: Vec<&String> = file.partition_values.keys().collect();
+    names.sort();
+    for name in names {
+        if batch.schema().index_of(name).is_ok() {
+            continue;
+        }
+        let data_type: ArrowDataType = ArrowDataType::try_from(table_schema.field_with_name(name)?.data_type())?;
+        let array: ArrayRef = match file.partition_values.get(name).cloned().flatten() {
+            None => new_null_array(&data_type, rows),
+            Some(value) => {
+                let values: ArrayRef = Arc::new(StringArray::from(vec![value.as_str(); rows]));
+                cast(&values, &data_type)?
+            }
+        };
+        fields.push(ArrowField::new(name, data_type, true));
+        columns.push(array);
+    }
+    Ok(RecordBatch::try_new(Arc::new(ArrowSchema::new(fields)), columns)?)
+}
+
+// the arrow counterpart of align_to_schema
+fn align_batch(batch: RecordBatch, table_schema: &StructType, columns: Option<&[String]>) -> Result<RecordBatch, DeltaTableError> {
+    let rows: usize = batch.num_rows();
+    let mut fields: Vec<ArrowField> = Vec::new();
+    let mut arrays: Vec<ArrayRef> = Vec::new();
+    for field in table_schema.fields().iter().filter(|f| columns.is_none_or(|c| c.contains(f.name()))) {
+        let data_type: ArrowDataType = ArrowDataType::try_from(field.data_type())?;
+        let array: ArrayRef = match batch.column_by_name(field.name()) {
+            Some(array) if array.data_type() != &data_type => {
+                // safe casts fail instead of turning values that do not fit into nulls
+                let options: CastOptions = CastOptions { safe: false, ..Default::default() };
+                cast_with_options(array, &data_type, &options)?
+            }
+            Some(array) => array.clone(),
+            None => new_null_array(&data_type, rows),
+        };
+        fields.push(ArrowField::new(field.name(), data_type, true));
+        arrays.push(array);
+    }
+    Ok(RecordBatch::try_new(Arc::new(ArrowSchema::new(fields)), arrays)?)
+}
+
+// keeps the rows matching every filter, the filter value is cast to the column type
+fn filter_batch(batch: RecordBatch, filters: &[Filter]) -> Result<RecordBatch, DeltaTableError> {
+    let mut mask: Option<BooleanArray> = None;
+    for filter in filters {
+        let column: &ArrayRef = batch.column_by_name(&filter.column)
+            .ok_or_else(|| DeltaTableError::Generic(format!("Filter column {} not found.", filter.column)))?;
+        let value: String = match &filter.value {
+            serde_json::Value::String(s) => s.clone(),
+            other => other.to_string(),
+        };
+        let literal: ArrayRef = cast(&(Arc::new(StringArray::from(vec![value])) as ArrayRef), column.data_type())?;
+        let literal: Scalar<ArrayRef> = Scalar::new(literal);
+        let matches: BooleanArray = match filter.op {
+            FilterOp::Eq => cmp::eq(column, &literal)?,
+            FilterOp::NotEq => cmp::neq(column, &literal)?,
+            FilterOp::Lt => cmp::lt(column, &literal)?,
+            FilterOp::LtEq => cmp::lt_eq(column, &literal)?,
+            FilterOp::Gt => cmp::gt(column, &literal)?,
+            FilterOp::GtEq => cmp::gt_eq(column, &literal)?,
+        };
+        mask = Some(match mask {
+            Some(m) => and(&m, &matches)?,
+            None => matches,
+        });
+    }
+    match mask {
+        Some(m) => Ok(filter_record_batch(&batch, &m)?),
+        None => Ok(batch),
+    }
+}
+
+// adds the file's partition values as columns typed from the table schema
+fn add_partition_columns(df: &mut polars::prelude::DataFrame, file: &DeltaFile, schema: &StructType) -> Result<(), DeltaTableError> {
+    let height: usize = df.height();
+    let mut names: Vec<&String> = file.partition_values.keys().collect();
+    names.sort();
+    for name in names {
+        if df.get_column_index(name).is_some() {
+            continue;
+        }
+        let data_type: &DeltaDataType = schema.field_with_name(name)?.data_type();
+        let dtype: polars::prelude::DataType = polars_type(data_type);
+        let series: Series = match file.partition_values.get(name).cloned().flatten() {
+            None => Series::full_null(name, height, &dtype),
+            Some(value) if dtype == polars::prelude::DataType::Boolean => {
+                Series::new(name, vec![value.eq_ignore_ascii_case("true"); height])
+            }
+            Some(value) => Series::new(name, vec![value.as_str(); height]).cast(&dtype)
+                .map_err(|e| DeltaTableError::Generic(format!("Failed to cast partition column {}: {}", name, e)))?,
+        };
+        df.with_column(series)
+            .map_err(|e| DeltaTableError::Generic(format!("Failed to add partition column {}: {}", name, e)))?;
+    }
+    Ok(())
+}
+
+// the polars type used for a delta column, decimals are read as floats
+fn polars_type(data_type: &DeltaDataType) -> polars::prelude::DataType {
+    match data_type {
+        DeltaDataType::Primitive(primitive) => match primitive {
+            PrimitiveType::String => polars::prelude::DataType::String,
+            PrimitiveType::Long => polars::prelude::DataType::Int64,
+            PrimitiveType::Integer => polars::prelude::DataType::Int32,
+            PrimitiveType::Short => polars::prelude::DataType::Int16,
+            PrimitiveType::Byte => polars::prelude::DataType::Int8,
+            PrimitiveType::Float => polars::prelude::DataType::Float32,
+            PrimitiveType::Double => polars::prelude::DataType::Float64,
+            PrimitiveType::Boolean => polars::prelude::DataType::Boolean,
+            PrimitiveType::Binary => polars::prelude::DataType::Binary,
+            PrimitiveType::Date => polars::prelude::DataType::Date,
+            PrimitiveType::Timestamp => polars::prelude::DataType::Datetime(TimeUnit::Microseconds, Some(String::from("UTC"))),
+            PrimitiveType::TimestampNtz => polars::prelude::DataType::Datetime(TimeUnit::Microseconds, None),
+            PrimitiveType::Decimal(_, _) => polars::prelude::DataType::Float64,
+        },
+        DeltaDataType::Array(array) => polars::prelude::DataType::List(Box::new(polars_type(array.element_type()))),
+        DeltaDataType::Struct(fields) => polars::prelude::DataType::Struct(
+            fields.fields().iter().map(|f| Field::new(f.name(), polars_type(f.data_type()))).collect()
+        ),
+        // parquet maps are read as a list of key value structs
+        DeltaDataType::Map(map) => polars::prelude::DataType::List(Box::new(polars::prelude::DataType::Struct(vec![
+            Field::new("key", polars_type(map.key_type())),
+            Field::new("value", polars_type(map.value_type())),
+        ]))),
+    }
+}
+
+// the polars schema of the table, without the columns hidden from the principal
+fn polars_schema(schema: &StructType, hidden: &[String]) -> Schema {
+    schema.fields().iter()
+        .filter(|f| !hidden.contains(f.name()))
+        .map(|f| Field::new(f.name(), polars_type(f.data_type())))
+        .collect()
+}
+
+// the columns the row filter and column masks of a table read
+fn policy_columns(table: &Table) -> Vec<String> {
+    let mut columns: Vec<String> = Vec::new();
+    for column in table.columns.iter().flatten() {
+        if let Some(mask) = &column.mask {
+            columns.push(column.name.clone());
+            columns.extend(mask.using_column_names.clone().unwrap_or_default());
+        }
+    }
+    if let Some(filter) = &table.row_filter {
+        columns.extend(filter.input_column_names.clone());
+    }
+    columns.sort();
+    columns.dedup();
+    columns
+}
+
+// replaces identifiers found in the replacements map and calls of the functions map, with or without empty parentheses
+// keys are lowercase and matching ignores case, string literals and qualified names are left untouched
+fn substitute_identifiers(expr: &str, replacements: &HashMap<String, String>, functions: &HashMap<String, String>) -> String {
+    let mut result: String = String::new();
+    let chars: Vec<char> = expr.chars().collect();
+    let mut i: usize = 0;
+    while i < chars.len() {
+        let c: char = chars[i];
+        if c == '\'' {
+            // copy string literals as is, '' is an escaped quote
+            let start: usize = i;
+            i += 1;
+            while i < chars.len() {
+                if chars[i] == '\'' && chars.get(i + 1) == Some(&'\'') {
+                    i += 2;
+                } else if chars[i] == '\'' {
+                    i += 1;
+                    break;
+                } else {
+                    i += 1;
+                }
+            }
+            result.extend(&chars[start..i]);
+        } else if c == '`' {
+            // databricks quoted identifier, datafusion quotes with double quotes
+            let start: usize = i + 1;
+            i = start;
+            while i < chars.len() && chars[i] != '`' {
+                i += 1;
+            }
+            let ident: String = chars[start..i].iter().collect();
+            i += 1;
+            match replacements.get(&ident.to_lowercase()) {
+                Some(r) => result.push_str(r),
+                None => result.push_str(&format!("\"{}\"", ident)),
+            }
+        } else if c.is_alphabetic() || c == '_' {
+            let start: usize = i;
+            while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            let ident: String = chars[start..i].iter().collect();
+            let qualified: bool = start > 0 && chars[start - 1] == '.';
+            if let (Some(r), false) = (functions.get(&ident.to_lowercase()), qualified) {
+                // consume the empty argument list of the call
+                let mut j: usize = i;
+                while j < chars.len() && chars[j].is_whitespace() {
+                    j += 1;
+                }
+                if chars.get(j) == Some(&'(') {
+                    let mut k: usize = j + 1;
+                    while k < chars.len() && chars[k].is_whitespace() {
+                        k += 1;
+                    }
+                    if chars.get(k) == Some(&')') {
+                        i = k + 1;
+                    }
+                }
+                result.push_str(r);
+                continue;
+            }
+            match replacements.get(&ident.to_lowercase()) {
+                Some(r) if !qualified => result.push_str(r),
+                _ => result.push_str(&ident),
+            }
+        } else {
+            result.push(c);
+            i += 1;
+        }
+    }
+    result
+}
+
+// appends to or overwrites unity catalog tables the principal is allowed to modify
+pub struct DeltaLakeWriter {
+    storage_credentials: StorageOptions,
+    permissions_client: Permissions,
+    metastore_client: MetastoreClient,
+    principal: String,
+    audit_logger: Option<AuditLogger>,
+    credential_vending: bool,
+    credential_cache: Arc<Mutex<HashMap<String, TemporaryTableCredentials>>>, // keyed by table_id
+}
+impl DeltaLakeWriter {
+    /// Creates the delta lake writer struct
+    ///
+    /// # Arguments
+    ///
+    /// * `storage_credentials` - The credentials used to authenticate against storage, chosen per table by its storage location
+    /// * `permissions_client` - Permissions Object to validate user permissions against unity catalog 
+    /// * `metastore_client` - Metastore Client object to interact with Unity Catalog APIs for data objects. 
+    /// * `principal` - The active user's username. 
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///     let writer: DeltaLakeWriter = DeltaLakeWriter::new(config.storage.clone(), permissions_client.clone(), metastore_client.clone(), String::from(principal));
+    /// ```
+    pub fn new(storage_credentials: StorageOptions, permissions_client: Permissions, metastore_client: MetastoreClient, principal: String) -> Self {
+        StorageOptions::register_handlers();
+
+        DeltaLakeWriter {
+            storage_credentials,
+            permissions_client,
+            metastore_client,
+            principal,
+            audit_logger: None,
+            credential_vending: false,
+            credential_cache: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Records every write attempt, allowed or not, with the audit logger.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let writer: DeltaLakeWriter = DeltaLakeWriter::new(storage_options, permissions_client, metastore_client, principal).with_audit_logger(AuditLogger::new(sql_client.clone()));
+    /// ```
+    pub fn with_audit_logger(mut self, audit_logger: AuditLogger) -> Self {
+        self.audit_logger = Some(audit_logger);
+        self
+    }
+
+    /// Writes with short lived, table scoped READ_WRITE credentials from Unity Catalog instead of the writer's storage credentials.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let writer: DeltaLakeWriter = DeltaLakeWriter::new(storage_options, permissions_client, metastore_client, principal).with_credential_vending();
+    /// ```
+    pub fn with_credential_vending(mut self) -> Self {
+        self.credential_vending = true;
+        self
+    }
+
+    // a failure to write the audit record is logged but does not fail the write
+    async fn record_audit(&self, record: &AuditRecord) {
+        if let Some(audit_logger) = &self.audit_logger {
+            if let Err(e) = audit_logger.record(record).await {
+                log::error!("Failed to write audit record for {}: {}", record.table_name, e);
+            }
+        }
+    }
+
+    /// If the user has permission to modify the table, appends or overwrites it with the polars dataframe and returns the new table version.
+    ///
+    /// # Arguments
+    ///
+    /// * `table_name` - The fully qualified table name
+    /// * `df` - The rows to write, columns are matched to the table by name
+    /// * `mode` - `WriteMode::Append` or `WriteMode::Overwrite`
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let version: i64 = writer.write_polars("my_catalog.my_schema.my_table", &mut df, WriteMode::Append).await?;
+    /// ```
+    pub async fn write_polars(&self, table_name: &str, df: &mut polars::prelude::DataFrame, mode: WriteMode) -> Result<i64, DeltaTableError> {
+        let batches: Vec<RecordBatch> = polars_to_record_batches(df)?;
+        self.write(table_name, batches, mode, "write_polars").await
+    }
+
+    /// If the user has permission to modify the table, appends or overwrites it with the arrow record batches and returns the new table version.
+    ///
+    /// # Arguments
+    ///
+    /// * `table_name` - The fully qualified table name
+    /// * `batches` - The rows to write, columns are matched to the table by name
+    /// * `mode` - `WriteMode::Append` or `WriteMode::Overwrite`
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let version: i64 = writer.write_record_batches("my_catalog.my_schema.my_table", batches, WriteMode::Overwrite).await?;
+    /// ```
+    pub async fn write_record_batches(&self, table_name: &str, batches: Vec<RecordBatch>, mode: WriteMode) -> Result<i64, DeltaTableError> {
+        self.write(table_name, batches, mode, "write_record_batches").await
+    }
+
+    /// If the user has permission to modify the table, merges the polars dataframe into it and returns what changed.
+    ///
+    /// # Arguments
+    ///
+    /// * `table_name` - The fully qualified table name
+    /// * `df` - The source rows
+    /// * `options` - The join keys and the update, insert and delete clauses
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let options: MergeOptions = MergeOptions::new(&["sku"]).when_matched_update_all().when_not_matched_insert_all();
+    /// let metrics: OperationMetrics = writer.merge_polars("my_catalog.my_schema.my_table", &mut df, &options).await?;
+    /// ```
+    pub async fn merge_polars(&self, table_name: &str, df: &mut polars::prelude::DataFrame, options: &MergeOptions) -> Result<OperationMetrics, DeltaTableError> {
+        let batches: Vec<RecordBatch> = polars_to_record_batches(df)?;
+        self.merge(table_name, batches, options, "merge_polars").await
+    }
+
+    /// If the user has permission to modify the table, merges the arrow record batches into it and returns what changed.
+    ///
+    /// # Arguments
+    ///
+    /// * `table_name` - The fully qualified table name
+    /// * `batches` - The source rows
+    /// * `options` - The join keys and the update, insert and delete clauses
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let metrics: OperationMetrics = writer.merge_record_batches("my_catalog.my_schema.my_table", batches, &options).await?;
+    /// ```
+    pub async fn merge_record_batches(&self, table_name: &str, batches: Vec<RecordBatch>, options: &MergeOptions) -> Result<OperationMetrics, DeltaTableError> {
+        self.merge(table_name, batches, options, "merge_record_batches").await
+    }
+
+    /// If the user has permission to modify the table, deletes the rows matching the predicate and returns what changed.
+    ///
+    /// # Arguments
+    ///
+    /// * `table_name` - The fully qualified table name
+    /// * `predicate` - A sql condition on the table columns
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let metrics: OperationMetrics = writer.delete_rows("my_catalog.my_schema.my_table", "date < '2024-01-01'").await?;
+    /// ```
+    pub async fn delete_rows(&self, table_name: &str, predicate: &str) -> Result<OperationMetrics, DeltaTableError> {
+        let mut audit: AuditRecord = AuditRecord::start(&self.principal, table_name, "delete_rows");
+        let result: Result<OperationMetrics, DeltaTableError> = match self.open_for_write(table_name, &mut audit).await {
+            Ok(table) => {
+                log::info!("Deleting rows of {} where {}", table_name, predicate);
+                delete_where(table, predicate).await.map(|(_, metrics)| metrics)
+            }
+            Err(e) => Err(e),
+        };
+        self.finish_operation(table_name, &mut audit, &result).await;
+        result
+    }
+
+    async fn merge(&self, table_name: &str, batches: Vec<RecordBatch>, options: &MergeOptions, operation: &str) -> Result<OperationMetrics, DeltaTableError> {
+        let mut audit: AuditRecord = AuditRecord::start(&self.principal, table_name, operation);
+        let result: Result<OperationMetrics, DeltaTableError> = match self.open_for_write(table_name, &mut audit).await {
+            Ok(table) => {
+                log::info!("Merging {} rows into {} on {:?}", batches.iter().map(|b| b.num_rows()).sum::<usize>(), table_name, options.keys);
+                merge_batches(table, batches, options).await.map(|(_, metrics)| metrics)
+            }
+            Err(e) => Err(e),
+        };
+        self.finish_operation(table_name, &mut audit, &result).await;
+        result
+    }
+
+    async fn finish_operation(&self, table_name: &str, audit: &mut AuditRecord, result: &Result<OperationMetrics, DeltaTableError>) {
+        if let Ok(metrics) = result {
+            log::info!("{} version {}: {:?}", table_name, metrics.version, metrics);
+            audit.table_version = Some(metrics.version);
+        }
+        audit.finish(result);
+        self.record_audit(audit).await;
+    }
+
+    async fn write(&self, table_name: &str, batches: Vec<RecordBatch>, mode: WriteMode, operation: &str) -> Result<i64, DeltaTableError> {
+        let mut audit: AuditRecord = AuditRecord::start(&self.principal, table_name, operation);
+        let result: Result<i64, DeltaTableError> = match self.open_for_write(table_name, &mut audit).await {
+            Ok(table) => {
+                log::info!("Writing {} rows to {} with mode {:?}", batches.iter().map(|b| b.num_rows()).sum::<usize>(), table_name, mode);
+                write_batches(table, batches, mode).await.map(|table| table.version())
+            }
+            Err(e) => Err(e),
+        };
+        if let Ok(version) = &result {
+            audit.table_version = Some(*version);
+        }
+        audit.finish(&result);
+        self.record_audit(&audit).await;
+        result
+    }
+
+    // checks the principal can modify the table and loads its latest version
+    async fn open_for_write(&self, table_name: &str, audit: &mut AuditRecord) -> Result<deltalake::DeltaTable, DeltaTableError> {
+        let uc_table: Table = self.metastore_client.get_table(table_name).await
+            .map_err(|e| DeltaTableError::Generic(format!("Failed to get table {}: {}", table_name, e)))?;
+        let allowed: bool = self.permissions_client.can_write(table_name, &self.principal).await
+            .map_err(|e| DeltaTableError::Generic(format!("Failed to check permissions on {}: {}", table_name, e)))?;
+        if !allowed {
+            log::error!("Permissions of Object {} Denied.", table_name);
+            audit.deny("principal does not have modify access");
+            return Err(DeltaTableError::Generic(String::from("Permission Denied.")));
+        }
+        if uc_table.table_type == "VIEW" || uc_table.table_type == "MATERIALIZED_VIEW" {
+            audit.deny("views cannot be written");
+            return Err(DeltaTableError::Generic(format!("{} is a {} and cannot be written.", table_name, uc_table.table_type)));
+        }
+        // a principal who cannot see every row and column could overwrite or delete data hidden from them
+        if uc_table.has_access_policies() {
+            log::error!("Object {} is protected by a row filter or column mask.", table_name);
+            audit.deny("table is protected by a row filter or column mask");
+            return Err(DeltaTableError::Generic(format!("Table {} is protected by a row filter or column mask and cannot be written.", table_name)));
+        }
+        let hidden: Vec<String> = principal_hidden_columns(&self.permissions_client, &self.metastore_client.sql_client, self.metastore_client.workspace_name(), &self.principal, table_name).await?;
+        if !hidden.is_empty() {
+            log::error!("Columns {:?} of {} are hidden from {}.", hidden, table_name, self.principal);
+            audit.hidden_columns = Some(hidden.join(","));
+            audit.deny("table has columns hidden from the principal");
+            return Err(DeltaTableError::Generic(format!("Table {} has columns hidden from {} and cannot be written.", table_name, self.principal)));
+        }
+        log::info!("Validated Permissions on Object: {}", table_name);
+
+        let table_path: String = uc_table.storage_location.clone().unwrap_or_default();
+        let storage_options: HashMap<String, String> = table_storage_options(&self.storage_credentials, &self.permissions_client, self.credential_vending, &self.credential_cache, &uc_table, "READ_WRITE").await?;
+        DeltaTableBuilder::from_uri(&table_path).with_storage_options(storage_options).load().await
+    }
+}
+
+/// Converts a polars dataframe to arrow record batches that can be written with deltalake.
+///
+/// # Arguments
+///
+/// * `df` - The dataframe to convert
+///
+/// # Examples
+///
+/// ```
+/// let batches: Vec<RecordBatch> = polars_to_record_batches(&mut df)?;
+/// ```
+pub fn polars_to_record_batches(df: &mut polars::prelude::DataFrame) -> Result<Vec<RecordBatch>, DeltaTableError> {
+    // polars and deltalake use different arrow implementations, parquet is the format both can read and write
+    let mut buffer: Vec<u8> = Vec::new();
+    ParquetWriter::new(&mut buffer).finish(df)
+        .map_err(|e| DeltaTableError::Generic(format!("Failed to convert dataframe: {}", e)))?;
+    // polars string view types in the embedded arrow schema are not understood by deltalake's arrow
+    let options: ArrowReaderOptions = ArrowReaderOptions::new().with_skip_arrow_metadata(true);
+    let reader = ParquetRecordBatchReaderBuilder::try_new_with_options(Bytes::from(buffer), options)?
+        .with_batch_size(RECORD_BATCH_SIZE)
+        .build()?;
+    let batches: Vec<RecordBatch> = reader.collect::<Result<Vec<RecordBatch>, ArrowError>>()?;
+    Ok(batches)
+}
+
+/// Appends or overwrites a loaded table with the record batches after checking them against the table schema.
+/// No permissions are checked, so this can write a table opened directly from a path, i.e. a local test table.
+///
+/// # Arguments
+///
+/// * `table` - The loaded delta table
+/// * `batches` - The rows to write, cast to the table column types
+/// * `mode` - `WriteMode::Append` or `WriteMode::Overwrite`
+///
+/// # Examples
+///
+/// ```
+/// let table: DeltaTable = deltalake::open_table("/tmp/my_table").await?;
+/// let table: DeltaTable = write_batches(table, batches, WriteMode::Append).await?;
+/// ```
+pub async fn write_batches(table: deltalake::DeltaTable, batches: Vec<RecordBatch>, mode: WriteMode) -> Result<deltalake::DeltaTable, DeltaTableError> {
+    let table_schema: StructType = table.get_schema()?.clone();
+    let batches: Vec<RecordBatch> = batches.into_iter()
+        .map(|batch| conform_batch(batch, &table_schema))
+        .collect::<Result<Vec<RecordBatch>, DeltaTableError>>()?;
+    DeltaOps(table).write(batches).with_save_mode(mode.save_mode()).await
+}
+
+/// Merges the record batches into a loaded table, no permissions are checked.
+///
+/// # Arguments
+///
+/// * `table` - The loaded delta table
+/// * `batches` - The source rows
+/// * `options` - The join keys and the update, insert and delete clauses
+///
+/// # Examples
+///
+/// ```
+/// let (table, metrics) = merge_batches(table, batches, &MergeOptions::new(&["sku"]).when_matched_update_all().when_not_matched_insert_all()).await?;
+/// ```
+pub async fn merge_batches(table: deltalake::DeltaTable, batches: Vec<RecordBatch>, options: &MergeOptions) -> Result<(deltalake::DeltaTable, OperationMetrics), DeltaTableError> {
+    if options.keys.is_empty() || options.clauses.is_empty() {
+        return Err(DeltaTableError::Generic(String::from("A merge needs at least one key and one clause.")));
+    }
+    // only the columns the source has are checked and cast, columns it leaves out are not touched
+    let table_schema: StructType = table.get_schema()?.clone();
+    let mut batches: Vec<RecordBatch> = batches.into_iter()
+        .map(|batch| conform_source_batch(batch, &table_schema))
+        .collect::<Result<Vec<RecordBatch>, DeltaTableError>>()?;
+    // an empty source still applies the not matched by source clauses
+    if batches.is_empty() {
+        batches.push(RecordBatch::new_empty(Arc::new(ArrowSchema::try_from(&table_schema)?)));
+    }
+    // used by the update all and insert all clauses
+    let all_columns: Vec<(String, String)> = batches[0].schema().fields().iter()
+        .map(|f| (f.name().clone(), format!("source.\"{}\"", f.name())))
+        .collect();
+
+    let ctx: SessionContext = SessionContext::new();
+    let source: deltalake::datafusion::prelude::DataFrame = ctx.read_batches(batches)?;
+    let mut builder: MergeBuilder = DeltaOps(table).merge(source, options.predicate())
+        .with_source_alias("source")
+        .with_target_alias("target");
+    for clause in options.clauses.clone() {
+        builder = match clause {
+            MergeClause::MatchedUpdate { predicate, set } => {
+                let set: Vec<(String, String)> = if set.is_empty() { all_columns.clone() } else { set };
+                builder.when_matched_update(|mut update| {
+                    if let Some(predicate) = predicate {
+                        update = update.predicate(predicate);
+                    }
+                    for (column, expr) in set {
+                        update = update.update(column.as_str(), expr);
+                    }
+                    update
+                })?
+            }
+            MergeClause::MatchedDelete { predicate } => builder.when_matched_delete(|mut delete| {
+                if let Some(predicate) = predicate {
+                    delete = delete.predicate(predicate);
+                }
+                delete
+            })?,
+            MergeClause::NotMatchedInsert { predicate, values } => {
+                let values: Vec<(String, String)> = if values.is_empty() { all_columns.clone() } else { values };
+                builder.when_not_matched_insert(|mut insert| {
+                    if let Some(predicate) = predicate {
+                        insert = insert.predicate(predicate);
+                    }
+                    for (column, expr) in values {
+                        insert = insert.set(column.as_str(), expr);
+                    }
+                    insert
+                })?
+            }
+            MergeClause::NotMatchedBySourceDelete { predicate } => builder.when_not_matched_by_source_delete(|mut delete| {
+                if let Some(predicate) = predicate {
+                    delete = delete.predicate(predicate);
+                }
+                delete
+            })?,
+        };
+    }
+
+    let (table, metrics): (deltalake::DeltaTable, MergeMetrics) = builder.await?;
+    let metrics: OperationMetrics = OperationMetrics {
+        version: table.version(),
+        rows_inserted: metrics.num_target_rows_inserted,
+        rows_updated: metrics.num_target_rows_updated,
+        rows_deleted: metrics.num_target_rows_deleted,
+        rows_copied: metrics.num_target_rows_copied,
+        files_added: metrics.num_target_files_added,
+        files_removed: metrics.num_target_files_removed,
+        execution_time_ms: metrics.execution_time_ms,
+    };
+    Ok((table, metrics))
+}
+
+/// Deletes the rows of a loaded table matching the predicate, no permissions are checked.
+///
+/// # Arguments
+///
+/// * `table` - The loaded delta table
+/// * `predicate` - A sql condition on the table columns
+///
+/// # Examples
+///
+/// ```
+/// let (table, metrics) = delete_where(table, "date < '2024-01-01'").await?;
+/// ```
+pub async fn delete_where(table: deltalake::DeltaTable, predicate: &str) -> Result<(deltalake::DeltaTable, OperationMetrics), DeltaTableError> {
+    let (table, metrics): (deltalake::DeltaTable, DeleteMetrics) = DeltaOps(table).delete().with_predicate(predicate).await?;
+    let metrics: OperationMetrics = OperationMetrics {
+        version: table.version(),
+        rows_deleted: metrics.num_deleted_rows.unwrap_or_default(),
+        rows_copied: metrics.num_copied_rows.unwrap_or_default(),
+        files_added: metrics.num_added_files,
+        files_removed: metrics.num_removed_files,
+        execution_time_ms: metrics.execution_time_ms as u64,
+        ..OperationMetrics::default()
+    };
+    Ok((table, metrics))
+}
+
+// rejects columns the table does not have and nulls in non nullable columns, then casts to the table schema
+// columns of the table missing from the batch are written as nulls
+fn conform_batch(batch: RecordBatch, table_schema: &StructType) -> Result<RecordBatch, DeltaTableError> {
+    check_batch_columns(&batch, table_schema)?;
+    if batch.num_rows() > 0 {
+        if let Some(field) = table_schema.fields().iter().find(|f| !f.is_nullable() && batch.column_by_name(f.name()).is_none()) {
+            return Err(DeltaTableError::Generic(format!("Column {} is not nullable but the data has nulls.", field.name())));
+        }
+    }
+    let aligned: RecordBatch = align_batch(batch, table_schema, None)?;
+    let schema: ArrowSchema = ArrowSchema::try_from(table_schema)?;
+    Ok(RecordBatch::try_new(Arc::new(schema), aligned.columns().to_vec())?)
+}
+
+// checks and casts only the columns a merge source has, the table columns it leaves out are not added
+fn conform_source_batch(batch: RecordBatch, table_schema: &StructType) -> Result<RecordBatch, DeltaTableError> {
+    check_batch_columns(&batch, table_schema)?;
+    let columns: Vec<String> = batch.schema().fields().iter().map(|f| f.name().clone()).collect();
+    align_batch(batch, table_schema, Some(&columns))
+}
+
+// rejects columns the table does not have and nulls in the non nullable columns the batch has
+fn check_batch_columns(batch: &RecordBatch, table_schema: &StructType) -> Result<(), DeltaTableError> {
+    let unknown: Vec<String> = batch.schema().fields().iter()
+        .map(|f| f.name().clone())
+        .filter(|name| !table_schema.fields().iter().any(|f| f.name() == name))
+        .collect();
+    if !unknown.is_empty() {
+        return Err(DeltaTableError::Generic(format!("Columns {} are not in the table schema.", unknown.join(", "))));
+    }
+    for field in table_schema.fields().iter().filter(|f| !f.is_nullable()) {
+        if batch.column_by_name(field.name()).is_some_and(|c| c.null_count() > 0) {
+            return Err(DeltaTableError::Generic(format!("Column {} is not nullable but the data has nulls.", field.name())));
+        }
+    }
+    Ok(())
+}
+
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use deltalake::arrow::array::{Int32Array, Int64Array};
+    use deltalake::kernel::StructField;
+    use tempfile::TempDir;
+
+    // an empty table with a required id and an optional name
+    async fn create_table(dir: &TempDir) -> deltalake::DeltaTable {
+        DeltaOps::try_from_uri(dir.path().to_str().unwrap()).await.unwrap()
+            .create()
+            .with_columns(vec![
+                StructField::new("id", DeltaDataType::Primitive(PrimitiveType::Long), false),
+                StructField::new("name", DeltaDataType::Primitive(PrimitiveType::String), true),
+            ])
+            .await
+            .unwrap()
+    }
+
+    fn batch(ids: Vec<i64>, names: Vec<Option<&str>>) -> RecordBatch {
+        let schema: ArrowSchema = ArrowSchema::new(vec![
+            ArrowField::new("id", ArrowDataType::Int64, false),
+            ArrowField::new("name", ArrowDataType::Utf8, true),
+        ]);
+        RecordBatch::try_new(Arc::new(schema), vec![Arc::new(Int64Array::from(ids)), Arc::new(StringArray::from(names))]).unwrap()
+    }
+
+    async fn row_count(table: &deltalake::DeltaTable) -> usize {
+        let ctx: SessionContext = SessionContext::new();
+        ctx.register_table("t", Arc::new(table.clone())).unwrap();
+        ctx.sql("SELECT * FROM t").await.unwrap().collect().await.unwrap().iter().map(|b| b.num_rows()).sum()
+    }
+
+    #[test]
+    fn hidden_columns_cannot_be_selected_or_filtered() {
+        let hidden: Vec<String> = vec![String::from("forecast_cost")];
+        assert!(hidden_columns_used(&ReadOptions::new(), &hidden).is_empty());
+        assert!(hidden_columns_used(&ReadOptions::new().with_columns(&["forecast_date"]), &hidden).is_empty());
+        assert_eq!(hidden_columns_used(&ReadOptions::new().with_filter(Filter::gt("forecast_cost", 1000000)), &hidden), hidden);
+        let both: ReadOptions = ReadOptions::new().with_columns(&["forecast_cost"]).with_filter(Filter::gt("forecast_cost", 0));
+        assert_eq!(hidden_columns_used(&both, &hidden), hidden);
+    }
+
+    #[test]
+    fn substitutes_user_functions_at_the_token_level() {
+        let replacements: HashMap<String, String> = HashMap::from([(String::from("ssn"), String::from("\"ssn\""))]);
+        let functions: HashMap<String, String> = HashMap::from([
+            (String::from("current_user"), String::from("'a@b.com'")),
+            (String::from("session_user"), String::from("'a@b.com'")),
+        ]);
+        let substitute = |expr: &str| substitute_identifiers(expr, &replacements, &functions);
+        assert_eq!(substitute("CASE WHEN Current_User() = 'x' THEN SSN ELSE NULL END"), "CASE WHEN 'a@b.com' = 'x' THEN \"ssn\" ELSE NULL END");
+        assert_eq!(substitute("session_user ( ) = current_user"), "'a@b.com' = 'a@b.com'");
+        assert_eq!(substitute("'current_user()' = my_current_user"), "'current_user()' = my_current_user");
+        assert_eq!(substitute("t.current_user()"), "t.current_user()");
+    }
+
+    #[tokio::test]
+    async fn append_adds_rows() {
+        let dir: TempDir = TempDir::new().unwrap();
+        let table: deltalake::DeltaTable = create_table(&dir).await;
+        let table: deltalake::DeltaTable = write_batches(table, vec![batch(vec![1, 2], vec![Some("a"), None])], WriteMode::Append).await.unwrap();
+        let table: deltalake::DeltaTable = write_batches(table, vec![batch(vec![3], vec![Some("c")])], WriteMode::Append).await.unwrap();
+        assert_eq!(table.version(), 2);
+        assert_eq!(row_count(&table).await, 3);
+    }
+
+    #[tokio::test]
+    async fn overwrite_replaces_rows() {
+        let dir: TempDir = TempDir::new().unwrap();
+        let table: deltalake::DeltaTable = create_table(&dir).await;
+        let table: deltalake::DeltaTable = write_batches(table, vec![batch(vec![1, 2, 3], vec![None, None, None])], WriteMode::Append).await.unwrap();
+        let table: deltalake::DeltaTable = write_batches(table, vec![batch(vec![4], vec![Some("d")])], WriteMode::Overwrite).await.unwrap();
+        assert_eq!(row_count(&table).await, 1);
+    }
+
+    #[tokio::test]
+    async fn rejects_columns_not_in_table() {
+        let dir: TempDir = TempDir::new().unwrap();
+        let table: deltalake::DeltaTable = create_table(&dir).await;
+        let schema: ArrowSchema = ArrowSchema::new(vec![
+            ArrowField::new("id", ArrowDataType::Int64, false),
+            ArrowField::new("price", ArrowDataType::Int64, true),
+        ]);
+        let extra: RecordBatch = RecordBatch::try_new(Arc::new(schema), vec![Arc::new(Int64Array::from(vec![1])), Arc::new(Int64Array::from(vec![10]))]).unwrap();
+        let result = write_batches(table, vec![extra], WriteMode::Append).await;
+        assert!(result.unwrap_err().to_string().contains("price"));
+
+        let table: deltalake::DeltaTable = deltalake::open_table(dir.path().to_str().unwrap()).await.unwrap();
+        assert_eq!(table.version(), 0);
+    }
+
+    #[tokio::test]
+    async fn rejects_nulls_in_required_columns() {
+        let dir: TempDir = TempDir::new().unwrap();
+        let table: deltalake::DeltaTable = create_table(&dir).await;
+        let schema: ArrowSchema = ArrowSchema::new(vec![ArrowField::new("name", ArrowDataType::Utf8, true)]);
+        let missing_id: RecordBatch = RecordBatch::try_new(Arc::new(schema), vec![Arc::new(StringArray::from(vec![Some("a")]))]).unwrap();
+        assert!(write_batches(table, vec![missing_id], WriteMode::Append).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn casts_narrower_types_to_the_table_type() {
+        let dir: TempDir = TempDir::new().unwrap();
+        let table: deltalake::DeltaTable = create_table(&dir).await;
+        let schema: ArrowSchema = ArrowSchema::new(vec![ArrowField::new("id", ArrowDataType::Int32, false)]);
+        let narrow: RecordBatch = RecordBatch::try_new(Arc::new(schema), vec![Arc::new(Int32Array::from(vec![1, 2]))]).unwrap();
+        let table: deltalake::DeltaTable = write_batches(table, vec![narrow], WriteMode::Append).await.unwrap();
+        assert_eq!(row_count(&table).await, 2);
+    }
+
+    #[tokio::test]
+    async fn merge_updates_and_inserts_on_keys() {
+        let dir: TempDir = TempDir::new().unwrap();
+        let table: deltalake::DeltaTable = create_table(&dir).await;
+        let table: deltalake::DeltaTable = write_batches(table, vec![batch(vec![1, 2], vec![Some("a"), Some("b")])], WriteMode::Append).await.unwrap();
+        let options: MergeOptions = MergeOptions::new(&["id"]).when_matched_update_all().when_not_matched_insert_all();
+        let (table, metrics) = merge_batches(table, vec![batch(vec![2, 3], vec![Some("B"), Some("c")])], &options).await.unwrap();
+        assert_eq!(metrics.rows_updated, 1);
+        assert_eq!(metrics.rows_inserted, 1);
+        assert_eq!(metrics.version, table.version());
+        assert_eq!(row_count(&table).await, 3);
+    }
+
+    #[tokio::test]
+    async fn merge_with_partial_source_keeps_omitted_columns() {
+        let dir: TempDir = TempDir::new().unwrap();
+        let table: deltalake::DeltaTable = DeltaOps::try_from_uri(dir.path().to_str().unwrap()).await.unwrap()
+            .create()
+            .with_columns(vec![
+                StructField::new("id", DeltaDataType::Primitive(PrimitiveType::Long), false),
+                StructField::new("name", DeltaDataType::Primitive(PrimitiveType::String), true),
+                StructField::new("price", DeltaDataType::Primitive(PrimitiveType::Long), false),
+            ])
+            .await
+            .unwrap();
+        let schema: ArrowSchema = ArrowSchema::new(vec![
+            ArrowField::new("id", ArrowDataType::Int64, false),
+            ArrowField::new("name", ArrowDataType::Utf8, true),
+            ArrowField::new("price", ArrowDataType::Int64, false),
+        ]);
+        let rows: RecordBatch = RecordBatch::try_new(Arc::new(schema), vec![
+            Arc::new(Int64Array::from(vec![1, 2])),
+            Arc::new(StringArray::from(vec![Some("a"), Some("b")])),
+            Arc::new(Int64Array::from(vec![10, 20])),
+        ]).unwrap();
+        let table: deltalake::DeltaTable = write_batches(table, vec![rows], WriteMode::Append).await.unwrap();
+
+        // the source only has the key and the changed price
+        let schema: ArrowSchema = ArrowSchema::new(vec![
+            ArrowField::new("id", ArrowDataType::Int64, false),
+            ArrowField::new("price", ArrowDataType::Int64, false),
+        ]);
+        let source: RecordBatch = RecordBatch::try_new(Arc::new(schema), vec![Arc::new(Int64Array::from(vec![2])), Arc::new(Int64Array::from(vec![25]))]).unwrap();
+        let options: MergeOptions = MergeOptions::new(&["id"]).when_matched_update_all();
+        let (table, metrics) = merge_batches(table, vec![source], &options).await.unwrap();
+        assert_eq!(metrics.rows_updated, 1);
+
+        let ctx: SessionContext = SessionContext::new();
+        ctx.register_table("t", Arc::new(table)).unwrap();
+        let batches: Vec<RecordBatch> = ctx.sql("SELECT name, price FROM t ORDER BY id").await.unwrap().collect().await.unwrap();
+        let names: Vec<Option<String>> = batches.iter()
+            .flat_map(|b| b.column(0).as_any().downcast_ref::<StringArray>().unwrap().iter().map(|v| v.map(String::from)).collect::<Vec<_>>())
+            .collect();
+        let prices: Vec<Option<i64>> = batches.iter()
+            .flat_map(|b| b.column(1).as_any().downcast_ref::<Int64Array>().unwrap().iter().collect::<Vec<_>>())
+            .collect();
+        assert_eq!(names, vec![Some(String::from("a")), Some(String::from("b"))]);
+        assert_eq!(prices, vec![Some(10), Some(25)]);
+    }
+
+    #[tokio::test]
+    async fn merge_with_empty_source_deletes_unmatched_rows() {
+        let dir: TempDir = TempDir::new().unwrap();
+        let table: deltalake::DeltaTable = create_table(&dir).await;
+        let table: deltalake::DeltaTable = write_batches(table, vec![batch(vec![1, 2], vec![None, None])], WriteMode::Append).await.unwrap();
+        let options: MergeOptions = MergeOptions::new(&["id"]).when_not_matched_by_source_delete(None);
+        let (table, metrics) = merge_batches(table, Vec::new(), &options).await.unwrap();
+        assert_eq!(metrics.rows_deleted, 2);
+        assert_eq!(row_count(&table).await, 0);
+    }
+
+    #[tokio::test]
+    async fn merge_rejects_columns_not_in_table() {
+        let dir: TempDir = TempDir::new().unwrap();
+        let table: deltalake::DeltaTable = create_table(&dir).await;
+        let schema: ArrowSchema = ArrowSchema::new(vec![
+            ArrowField::new("id", ArrowDataType::Int64, false),
+            ArrowField::new("price", ArrowDataType::Int64, true),
+        ]);
+        let extra: RecordBatch = RecordBatch::try_new(Arc::new(schema), vec![Arc::new(Int64Array::from(vec![1])), Arc::new(Int64Array::from(vec![10]))]).unwrap();
+        let options: MergeOptions = MergeOptions::new(&["id"]).when_not_matched_insert_all();
+        assert!(merge_batches(table, vec![extra], &options).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn delete_where_removes_matching_rows() {
+        let dir: TempDir = TempDir::new().unwrap();
+        let table: deltalake::DeltaTable = create_table(&dir).await;
+        let table: deltalake::DeltaTable = write_batches(table, vec![batch(vec![1, 2, 3], vec![None, None, None])], WriteMode::Append).await.unwrap();
+        let (table, metrics) = delete_where(table, "id > 1").await.unwrap();
+        assert_eq!(metrics.rows_deleted, 2);
+        assert_eq!(row_count(&table).await, 1);
+    }
+
+    #[test]
+    fn commit_versions_come_from_commit_file_names() {
+        assert_eq!(commit_version(&Path::from("_delta_log/00000000000000000010.json")), Some(10));
+        assert_eq!(commit_version(&Path::from("_delta_log/00000000000000000010.checkpoint.parquet")), None);
+        assert_eq!(commit_version(&Path::from("_delta_log/_last_checkpoint")), None);
+        assert_eq!(commit_version(&Path::from("_delta_log/10.json")), None);
+    }
+
+    #[test]
+    fn commit_timestamps_use_the_in_commit_timestamp_once_enabled() {
+        let info: CommitInfo = CommitInfo {
+            timestamp: Some(1),
+            info: HashMap::from([(String::from("inCommitTimestamp"), serde_json::json!(2000))]),
+            ..Default::default()
+        };
+        let disabled: CommitClock = CommitClock { in_commit_since: None };
+        assert_eq!(disabled.timestamp(5, Some(&info), 3000), 3000);
+        let enabled: CommitClock = CommitClock { in_commit_since: Some(5) };
+        assert_eq!(enabled.timestamp(4, Some(&info), 3000), 3000);
+        assert_eq!(enabled.timestamp(5, Some(&info), 3000), 2000);
+        assert_eq!(enabled.timestamp(5, None, 3000), 3000);
+    }
+
+    #[tokio::test]
+    async fn history_versions_skip_cleaned_up_commits() {
+        let dir: TempDir = TempDir::new().unwrap();
+        let table: deltalake::DeltaTable = create_table(&dir).await;
+        let table: deltalake::DeltaTable = write_batches(table, vec![batch(vec![1], vec![None])], WriteMode::Append).await.unwrap();
+        let table: deltalake::DeltaTable = write_batches(table, vec![batch(vec![2], vec![None])], WriteMode::Append).await.unwrap();
+        std::fs::remove_file(dir.path().join("_delta_log").join(format!("{:020}.json", 1))).unwrap();
+
+        let history: Vec<TableCommit> = commit_history(&table, None).await.unwrap();
+        assert_eq!(history.iter().map(|c| c.version).collect::<Vec<i64>>(), vec![2, 0]);
+        assert_eq!(history[0].operation.as_deref(), Some("WRITE"));
+        let latest: Vec<TableCommit> = commit_history(&table, Some(1)).await.unwrap();
+        assert_eq!(latest.len(), 1);
+        assert_eq!(latest[0].version, 2);
+    }
+
+    #[tokio::test]
+    async fn writes_polars_dataframes() {
+        let dir: TempDir = TempDir::new().unwrap();
+        let table: deltalake::DeltaTable = create_table(&dir).await;
+        let mut df: polars::prelude::DataFrame = df!("id" => [1i64, 2, 3], "name" => ["a", "b", "c"]).unwrap();
+        let batches: Vec<RecordBatch> = polars_to_record_batches(&mut df).unwrap();
+        let table: deltalake::DeltaTable = write_batches(table, batches, WriteMode::Append).await.unwrap();
+        assert_eq!(row_count(&table).await, 3);
+    }
+}
@@ -0,0 +1,251 @@
+use polars::prelude::{col, lit, DataType, Expr};
+use serde::Deserialize;
+use serde_json::Value;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum FilterOp {
+    Eq,
+    NotEq,
+    Lt,
+    LtEq,
+    Gt,
+    GtEq,
+}
+impl FilterOp {
+    fn sql(&self) -> &'static str {
+        match self {
+            FilterOp::Eq => "=",
+            FilterOp::NotEq => "<>",
+            FilterOp::Lt => "<",
+            FilterOp::LtEq => "<=",
+            FilterOp::Gt => ">",
+            FilterOp::GtEq => ">=",
+        }
+    }
+}
+
+// a comparison between a column and a literal, filters passed to a read are combined with AND
+#[derive(Debug, Clone)]
+pub struct Filter {
+    pub column: String,
+    pub op: FilterOp,
+    pub value: Value,
+}
+impl Filter {
+    /// Creates a filter comparing a column to a value, dates and timestamps are given as strings.
+    ///
+    /// # Arguments
+    ///
+    /// * `column` - The column name
+    /// * `op` - The comparison
+    /// * `value` - A string, number or boolean
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let filter: Filter = Filter::new("date", FilterOp::GtEq, "2024-01-01");
+    /// ```
+    pub fn new(column: &str, op: FilterOp, value: impl Into<Value>) -> Self {
+        Filter { column: column.to_string(), op, value: value.into() }
+    }
+
+    pub fn eq(column: &str, value: impl Into<Value>) -> Self {
+        Filter::new(column, FilterOp::Eq, value)
+    }
+
+    pub fn not_eq(column: &str, value: impl Into<Value>) -> Self {
+        Filter::new(column, FilterOp::NotEq, value)
+    }
+
+    pub fn lt(column: &str, value: impl Into<Value>) -> Self {
+        Filter::new(column, FilterOp::Lt, value)
+    }
+
+    pub fn lt_eq(column: &str, value: impl Into<Value>) -> Self {
+        Filter::new(column, FilterOp::LtEq, value)
+    }
+
+    pub fn gt(column: &str, value: impl Into<Value>) -> Self {
+        Filter::new(column, FilterOp::Gt, value)
+    }
+
+    pub fn gt_eq(column: &str, value: impl Into<Value>) -> Self {
+        Filter::new(column, FilterOp::GtEq, value)
+    }
+
+    // false only when no row of a file with this partition value can match, a null partition never matches
+    pub fn matches_partition(&self, partition_value: Option<&str>) -> bool {
+        let value: Value = match partition_value {
+            Some(v) => partition_literal(v, &self.value),
+            None => return false,
+        };
+        if self.op == FilterOp::NotEq {
+            return value != self.value;
+        }
+        match compare(&value, &self.value) {
+            Some(ordering) => match self.op {
+                FilterOp::Eq => ordering == Ordering::Equal,
+                FilterOp::NotEq => true,
+                FilterOp::Lt => ordering == Ordering::Less,
+                FilterOp::LtEq => ordering != Ordering::Greater,
+                FilterOp::Gt => ordering == Ordering::Greater,
+                FilterOp::GtEq => ordering != Ordering::Less,
+            },
+            None => true,
+        }
+    }
+
+    // false only when the min/max stats of a file rule out every row, missing stats keep the file
+    pub fn may_match_stats(&self, stats: &FileStats) -> bool {
+        let min: Option<Ordering> = stats.min_values.get(&self.column).and_then(|min| compare(min, &self.value));
+        let max: Option<Ordering> = stats.max_values.get(&self.column).and_then(|max| compare(max, &self.value));
+        match self.op {
+            FilterOp::Eq => min != Some(Ordering::Greater) && max != Some(Ordering::Less),
+            // a file can only be skipped when every value equals the filter value exactly
+            FilterOp::NotEq => !(stats.min_values.get(&self.column) == Some(&self.value) && stats.max_values.get(&self.column) == Some(&self.value)),
+            FilterOp::Lt => min.is_none_or(|o| o == Ordering::Less),
+            FilterOp::LtEq => min.is_none_or(|o| o != Ordering::Greater),
+            FilterOp::Gt => max.is_none_or(|o| o == Ordering::Greater),
+            FilterOp::GtEq => max.is_none_or(|o| o != Ordering::Less),
+        }
+    }
+
+    // the row level predicate, the literal is cast to the column type so dates can be compared to strings
+    pub fn to_expr(&self, dtype: Option<&DataType>) -> Expr {
+        let mut value: Expr = match &self.value {
+            Value::Bool(b) => lit(*b),
+            Value::Number(n) => match n.as_i64() {
+                Some(i) => lit(i),
+                None => lit(n.as_f64().unwrap_or_default()),
+            },
+            Value::String(s) => lit(s.as_str()),
+            other => lit(other.to_string()),
+        };
+        if let Some(dtype) = dtype {
+            value = value.cast(dtype.clone());
+        }
+        let column: Expr = col(&self.column);
+        match self.op {
+            FilterOp::Eq => column.eq(value),
+            FilterOp::NotEq => column.neq(value),
+            FilterOp::Lt => column.lt(value),
+            FilterOp::LtEq => column.lt_eq(value),
+            FilterOp::Gt => column.gt(value),
+            FilterOp::GtEq => column.gt_eq(value),
+        }
+    }
+
+    // the predicate as a datafusion sql condition
+    pub fn to_sql(&self) -> String {
+        let value: String = match &self.value {
+            Value::String(s) => format!("'{}'", s.replace('\'', "''")),
+            other => other.to_string(),
+        };
+        format!("\"{}\" {} {}", self.column, self.op.sql(), value)
+    }
+}
+
+// per file statistics written to the delta log, nested columns are not used for pruning
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FileStats {
+    pub num_records: Option<i64>,
+    #[serde(default)]
+    pub min_values: HashMap<String, Value>,
+    #[serde(default)]
+    pub max_values: HashMap<String, Value>,
+    #[serde(default)]
+    pub null_count: HashMap<String, Value>,
+}
+impl FileStats {
+    pub fn from_json(stats: &str) -> Option<FileStats> {
+        serde_json::from_str(stats).ok()
+    }
+}
+
+// partition values are always strings in the log, parse them like the filter value so numbers compare as numbers
+fn partition_literal(partition_value: &str, like: &Value) -> Value {
+    match like {
+        Value::Number(_) => partition_value.parse::<f64>().ok()
+            .and_then(serde_json::Number::from_f64)
+            .map(Value::Number)
+            .unwrap_or(Value::String(partition_value.to_string())),
+        Value::Bool(_) => Value::Bool(partition_value.eq_ignore_ascii_case("true")),
+        _ => Value::String(partition_value.to_string()),
+    }
+}
+
+// None when the values cannot be compared, i.e. a number and a string
+// strings compare on their common prefix, so a timestamp equals the date it falls on and truncated string stats still match
+fn compare(left: &Value, right: &Value) -> Option<Ordering> {
+    match (left, right) {
+        (Value::Number(l), Value::Number(r)) => l.as_f64()?.partial_cmp(&r.as_f64()?),
+        (Value::Bool(l), Value::Bool(r)) => Some(l.cmp(r)),
+        (Value::String(l), Value::String(r)) => {
+            let len: usize = l.len().min(r.len());
+            Some(l.as_bytes()[..len].cmp(&r.as_bytes()[..len]))
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stats(json: &str) -> FileStats {
+        FileStats::from_json(json).unwrap()
+    }
+
+    #[test]
+    fn partitions_compare_as_the_filter_type() {
+        assert!(Filter::gt("year", 2023).matches_partition(Some("2024")));
+        assert!(!Filter::gt("year", 2023).matches_partition(Some("2023")));
+        // numbers compare numerically, not as strings
+        assert!(Filter::lt("month", 10).matches_partition(Some("9")));
+        assert!(Filter::eq("region", "emea").matches_partition(Some("emea")));
+        assert!(!Filter::not_eq("region", "emea").matches_partition(Some("emea")));
+        assert!(!Filter::eq("region", "emea").matches_partition(None));
+    }
+
+    #[test]
+    fn stats_rule_out_files() {
+        let file: FileStats = stats(r#"{"numRecords": 10, "minValues": {"id": 5, "date": "2024-01-01"}, "maxValues": {"id": 10, "date": "2024-01-31"}, "nullCount": {"id": 0}}"#);
+        assert_eq!(file.num_records, Some(10));
+        assert!(Filter::eq("id", 7).may_match_stats(&file));
+        assert!(!Filter::eq("id", 11).may_match_stats(&file));
+        assert!(!Filter::lt("id", 5).may_match_stats(&file));
+        assert!(Filter::lt_eq("id", 5).may_match_stats(&file));
+        assert!(!Filter::gt("id", 10).may_match_stats(&file));
+        assert!(Filter::gt_eq("id", 10).may_match_stats(&file));
+        // a timestamp compares on the date it falls on
+        assert!(Filter::eq("date", "2024-01-15T10:00:00Z").may_match_stats(&file));
+        assert!(!Filter::gt_eq("date", "2024-02-01").may_match_stats(&file));
+        // missing stats keep the file
+        assert!(Filter::eq("name", "a").may_match_stats(&file));
+        assert!(Filter::eq("id", 11).may_match_stats(&FileStats::default()));
+    }
+
+    #[test]
+    fn not_eq_only_skips_files_of_a_single_value() {
+        let single: FileStats = stats(r#"{"minValues": {"region": "emea"}, "maxValues": {"region": "emea"}}"#);
+        let range: FileStats = stats(r#"{"minValues": {"region": "amer"}, "maxValues": {"region": "emea"}}"#);
+        assert!(!Filter::not_eq("region", "emea").may_match_stats(&single));
+        assert!(Filter::not_eq("region", "emea").may_match_stats(&range));
+    }
+
+    #[test]
+    fn sql_quotes_columns_and_escapes_strings() {
+        assert_eq!(Filter::eq("name", "o'brien").to_sql(), "\"name\" = 'o''brien'");
+        assert_eq!(Filter::gt_eq("id", 5).to_sql(), "\"id\" >= 5");
+        assert_eq!(Filter::not_eq("active", true).to_sql(), "\"active\" <> true");
+    }
+
+    #[test]
+    fn invalid_stats_are_ignored() {
+        assert!(FileStats::from_json("not json").is_none());
+    }
+}
@@ -1,4 +1,5 @@
 use super::api_client::APIClient;
+use super::secret::Secret;
 use crate::sql::sql_client::SqlClient as SqlClient;
 use reqwest::{Error, Response};
 use serde::Deserialize;
@@ -8,13 +9,13 @@ use sqlx::prelude::FromRow;
 #[derive(Clone)]
 pub struct MetastoreClient {
     api_client: APIClient,
-    sql_client: SqlClient,
+    pub sql_client: SqlClient,
 }
 
 impl MetastoreClient {
 
     
-    pub async fn new(workspace_name: String, db_token: String, database_url: String) -> Self {
+    pub async fn new(workspace_name: String, db_token: Secret, database_url: String) -> Self {
         let api_client: APIClient = APIClient{
             db_token: db_token,
             workspace_name: workspace_name
@@ -27,6 +28,52 @@ impl MetastoreClient {
         perms
     }
 
+    pub fn workspace_name(&self) -> &str {
+        &self.api_client.workspace_name
+    }
+
+    // The metastore assigned to the workspace
+    // https://docs.databricks.com/api/workspace/metastores/current
+    pub async fn fetch_metastore_assignment(&self) -> Result<MetastoreAssignment, Error> {
+        let url: String = format!(
+            "https://{}/api/2.1/unity-catalog/current-metastore-assignment",
+            &self.api_client.workspace_name
+        );
+
+        let response: Response = self.api_client.fetch(&url).await?;
+        let mut assignment: MetastoreAssignment = match response.json().await {
+            Ok(assignment) => assignment,
+            Err(e) => {
+                log::error!("Error deserializing JSON response: {}", e);
+                return Err(e);
+            }
+        };
+        assignment.workspace_name = self.api_client.workspace_name.clone();
+
+        Ok(assignment)
+    }
+
+    /// Mirrors the catalogs, schemas, tables, storage credentials and external locations of the workspace.
+    /// Rows are keyed by workspace and metastore so several workspaces can share one database.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// metastore_client.refresh_workspace().await?;
+    /// ```
+    pub async fn refresh_workspace(&self) -> Result<(), Error> {
+        log::info!("Refreshing workspace {}.", self.api_client.workspace_name);
+        let assignment: MetastoreAssignment = self.fetch_metastore_assignment().await?;
+        self.refresh_catalogs().await?;
+        self.refresh_all_schemas().await?;
+        self.refresh_all_tables().await?;
+        self.refresh_storage_credentials().await?;
+        self.refresh_external_locations().await?;
+        // only marked as synced once everything is written
+        self.sql_client.write_workspace(&assignment).await.unwrap();
+        Ok(())
+    }
+
     // List all catalogs in a Databricks' Unity Catalog Metastore
     // https://docs.databricks.com/api/workspace/catalogs/list
     async fn fetch_catalogs(&self) -> Result<CatalogResponse, Error> {
@@ -126,11 +173,25 @@ impl MetastoreClient {
         let mut tables: Vec<Table> = Vec::new(); // Create an empty vector of tables
         tables.push(table.clone());
         let table_response = TableResponse::new(tables);
-        self.sql_client.write_tables(table_response).await.unwrap();
+        self.sql_client.write_tables(&self.api_client.workspace_name, table_response).await.unwrap();
 
         Ok(table)
     }
 
+    // Get an individual function, used to resolve row filter and column mask definitions
+    // https://docs.databricks.com/api/workspace/functions/get
+    pub async fn get_function(&self, full_function_name: &str) -> Result<FunctionInfo, Error> {
+        let url: String = format!(
+            "https://{}/api/2.1/unity-catalog/functions/{}",
+            &self.api_client.workspace_name, full_function_name
+        );
+
+        let response: Response = self.api_client.fetch(&url).await?;
+        let function: FunctionInfo = response.json().await?;
+
+        Ok(function)
+    }
+
     // Get an individual schema object
     // https://docs.databricks.com/api/workspace/schemas/get
     pub async fn get_schema(&self, full_schema_name: String) -> Result<Schema, Error> {
@@ -146,7 +207,7 @@ impl MetastoreClient {
         let mut schemas: Vec<Schema> = Vec::new(); // Create an empty vector of schemas
         schemas.push(schema.clone());
         let schema_response = SchemaResponse::new(schemas);
-        self.sql_client.write_schemas(schema_response).await.unwrap();
+        self.sql_client.write_schemas(&self.api_client.workspace_name, schema_response).await.unwrap();
 
         Ok(schema)
     }
@@ -166,7 +227,7 @@ impl MetastoreClient {
         let mut catalogs: Vec<Catalog> = Vec::new(); // Create an empty vector of Catalogs
         catalogs.push(catalog.clone());
         let catalog_response = CatalogResponse::new(catalogs);
-        self.sql_client.write_catalogs(catalog_response).await.unwrap();
+        self.sql_client.write_catalogs(&self.api_client.workspace_name, catalog_response).await.unwrap();
 
         Ok(catalog)
     }
@@ -174,7 +235,7 @@ impl MetastoreClient {
     pub async fn refresh_catalogs(&self) -> Result<(), Error> {
         log::info!("Getting Catalogs. ");
         let catalogs: CatalogResponse = self.fetch_catalogs().await?;
-        self.sql_client.write_catalogs(catalogs).await.unwrap();
+        self.sql_client.write_catalogs(&self.api_client.workspace_name, catalogs).await.unwrap();
 
         Ok(())
     }
@@ -189,7 +250,7 @@ impl MetastoreClient {
                 && catalog.name != "adrian_hive_test"
             {
                 let schemas: SchemaResponse = self.fetch_schemas(catalog.name, None).await?;
-                self.sql_client.write_schemas(schemas).await.unwrap();
+                self.sql_client.write_schemas(&self.api_client.workspace_name, schemas).await.unwrap();
                 std::thread::sleep(std::time::Duration::from_secs(1));
             }
         }
@@ -221,7 +282,7 @@ impl MetastoreClient {
                             .await?;
                         if let Some(ref tables) = table_response.tables {
                             log::info!("Num Tables: {}", tables.len());
-                            self.sql_client.write_tables(table_response).await.unwrap();
+                            self.sql_client.write_tables(&self.api_client.workspace_name, table_response).await.unwrap();
                             // std::thread::sleep(std::time::Duration::from_secs(1));
                         }
                     }
@@ -231,11 +292,101 @@ impl MetastoreClient {
         Ok(())
     }
 
+    // List all storage credentials in the metastore
+    // https://docs.databricks.com/api/workspace/storagecredentials/list
+    async fn fetch_storage_credentials(&self) -> Result<StorageCredentialResponse, Error> {
+        let url: String = format!(
+            "https://{}/api/2.1/unity-catalog/storage-credentials",
+            &self.api_client.workspace_name
+        );
+
+        let response: Response = self.api_client.fetch(&url).await?;
+        let credentials: StorageCredentialResponse = match response.json().await {
+            Ok(credentials) => credentials,
+            Err(e) => {
+                log::error!("Error deserializing JSON response: {}", e);
+                return Err(e);
+            }
+        };
+
+        Ok(credentials)
+    }
+
+    // List all external locations in the metastore
+    // https://docs.databricks.com/api/workspace/externallocations/list
+    async fn fetch_external_locations(&self) -> Result<ExternalLocationResponse, Error> {
+        let url: String = format!(
+            "https://{}/api/2.1/unity-catalog/external-locations",
+            &self.api_client.workspace_name
+        );
+
+        let response: Response = self.api_client.fetch(&url).await?;
+        let locations: ExternalLocationResponse = match response.json().await {
+            Ok(locations) => locations,
+            Err(e) => {
+                log::error!("Error deserializing JSON response: {}", e);
+                return Err(e);
+            }
+        };
+
+        Ok(locations)
+    }
+
+    pub async fn refresh_storage_credentials(&self) -> Result<(), Error> {
+        log::info!("Getting Storage Credentials.");
+        let credentials: StorageCredentialResponse = self.fetch_storage_credentials().await?;
+        self.sql_client.write_storage_credentials(&self.api_client.workspace_name, credentials).await.unwrap();
+        Ok(())
+    }
+
+    pub async fn refresh_external_locations(&self) -> Result<(), Error> {
+        log::info!("Getting External Locations.");
+        let locations: ExternalLocationResponse = self.fetch_external_locations().await?;
+        self.sql_client.write_external_locations(&self.api_client.workspace_name, locations).await.unwrap();
+        Ok(())
+    }
+
+    /// Returns the external location covering a storage path, the location with the longest matching url wins.
+    /// Uses the local mirror, so external locations should be refreshed first.
+    ///
+    /// # Arguments
+    ///
+    /// * `storage_location` - The storage path of a table i.e. `abfss://container@account.dfs.core.windows.net/tables/sales`
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let location: Option<ExternalLocation> = metastore_client.resolve_external_location(&table.storage_location.unwrap()).await?;
+    /// ```
+    pub async fn resolve_external_location(&self, storage_location: &str) -> Result<Option<ExternalLocation>, sqlx::Error> {
+        let locations: Vec<ExternalLocation> = self.sql_client.list_external_locations(Some(&self.api_client.workspace_name)).await?;
+        Ok(longest_prefix_match(storage_location, &locations))
+    }
+
+    // the credential governing every table path in the local mirror
+    pub async fn resolve_table_credentials(&self) -> Result<Vec<TableCredentialResolution>, sqlx::Error> {
+        let locations: Vec<ExternalLocation> = self.sql_client.list_external_locations(Some(&self.api_client.workspace_name)).await?;
+        let mut resolutions: Vec<TableCredentialResolution> = Vec::new();
+
+        for table in self.sql_client.list_table_locations(Some(&self.api_client.workspace_name)).await? {
+            let location: Option<ExternalLocation> = table.storage_location.as_deref()
+                .and_then(|path| longest_prefix_match(path, &locations));
+            resolutions.push(TableCredentialResolution {
+                full_name: table.full_name,
+                storage_location: table.storage_location,
+                external_location_name: location.as_ref().map(|l| l.name.clone()),
+                // managed tables carry their credential directly, external tables use their location's
+                credential_name: table.storage_credential_name.or(location.and_then(|l| l.credential_name)),
+            });
+        }
+        Ok(resolutions)
+    }
+
     pub async fn refresh_tables(&self, catalog_name: String, schema_name: String) -> Result<(), Error> {
         let table_response = self.fetch_tables(catalog_name, schema_name, None).await?;
         if let Some(ref tables) = table_response.tables {
             log::info!("Num Tables: {}", tables.len());
-            self.sql_client.write_tables(table_response).await.unwrap();
+            self.sql_client.write_tables(&self.api_client.workspace_name, table_response).await.unwrap();
         }
       Ok(())
     }
@@ -244,13 +395,50 @@ impl MetastoreClient {
         let schema_response = self.fetch_schemas(catalog_name, None).await?;
         if let Some(ref schemas) = schema_response.schemas {
             log::info!("Num Schemas: {}", schemas.len());
-            self.sql_client.write_schemas(schema_response).await.unwrap();
+            self.sql_client.write_schemas(&self.api_client.workspace_name, schema_response).await.unwrap();
         }
       Ok(())
     }
 
 }
 
+/// Syncs several workspaces into the same local database, one client per workspace.
+/// A failing workspace is logged and skipped so the others are still refreshed.
+///
+/// # Arguments
+///
+/// * `clients` - One metastore client per workspace, all pointing at the same database url
+///
+/// # Examples
+///
+/// ```
+/// let clients: Vec<MetastoreClient> = vec![dev_client, prod_client];
+/// let failed: Vec<String> = refresh_workspaces(&clients).await;
+/// ```
+pub async fn refresh_workspaces(clients: &[MetastoreClient]) -> Vec<String> {
+    let mut failed: Vec<String> = Vec::new();
+    for client in clients {
+        if let Err(e) = client.refresh_workspace().await {
+            log::error!("Failed to refresh workspace {}: {}", client.workspace_name(), e);
+            failed.push(client.workspace_name().to_string());
+        }
+    }
+    failed
+}
+
+// a location matches when it equals the path or is a parent directory of it
+fn longest_prefix_match(storage_location: &str, locations: &[ExternalLocation]) -> Option<ExternalLocation> {
+    let path: &str = storage_location.trim_end_matches('/');
+    locations
+        .iter()
+        .filter(|l| {
+            let url: &str = l.url.trim_end_matches('/');
+            path == url || path.starts_with(&format!("{}/", url))
+        })
+        .max_by_key(|l| l.url.trim_end_matches('/').len())
+        .cloned()
+}
+
 // wrapper struct to contain a vector of catalogs
 #[derive(Debug, Deserialize, Clone)]
 pub struct CatalogResponse {
@@ -358,12 +546,137 @@ pub struct Table {
     pub access_point: Option<String>,
     pub pipeline_id: Option<String>,
     pub browse_only: Option<bool>,
+    #[sqlx(skip)]
+    pub columns: Option<Vec<ColumnInfo>>,
+    #[sqlx(skip)]
+    pub row_filter: Option<RowFilter>,
     // excluded fields due to nesting
-    // columns
     // dependencies
     // properties
     // table_constraints
-    // row_filter
     // delta_runtime_properties_kvpairs
     // effective_predictive_optimization_flag
 }
+impl Table {
+    // true when unity catalog applies a row filter or column mask to this table
+    pub fn has_access_policies(&self) -> bool {
+        let masked: bool = self.columns.as_ref().is_some_and(|cols| cols.iter().any(|c| c.mask.is_some()));
+        self.row_filter.is_some() || masked
+    }
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct ColumnInfo {
+    pub name: String,
+    pub type_text: Option<String>,
+    pub type_name: Option<String>,
+    pub position: Option<i64>,
+    pub nullable: Option<bool>,
+    pub comment: Option<String>,
+    pub partition_index: Option<i64>,
+    pub mask: Option<ColumnMask>,
+}
+
+// https://docs.databricks.com/en/tables/row-and-column-filters.html
+#[derive(Debug, Deserialize, Clone)]
+pub struct ColumnMask {
+    pub function_name: String,
+    pub using_column_names: Option<Vec<String>>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct RowFilter {
+    pub function_name: String,
+    pub input_column_names: Vec<String>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct FunctionInfo {
+    pub name: String,
+    pub catalog_name: String,
+    pub schema_name: String,
+    pub full_name: String,
+    pub owner: Option<String>,
+    pub routine_body: Option<String>, // SQL or EXTERNAL
+    pub routine_definition: Option<String>,
+    pub input_params: Option<FunctionParameterInfos>,
+    pub data_type: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct FunctionParameterInfos {
+    pub parameters: Option<Vec<FunctionParameterInfo>>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct FunctionParameterInfo {
+    pub name: String,
+    pub position: i64,
+    pub type_text: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct StorageCredentialResponse {
+    pub storage_credentials: Option<Vec<StorageCredential>>,
+}
+
+#[derive(Debug, Deserialize, Clone, FromRow)]
+pub struct StorageCredential {
+    pub id: String,
+    pub name: String,
+    pub owner: Option<String>,
+    pub comment: Option<String>,
+    pub read_only: Option<bool>,
+    pub used_for_managed_storage: Option<bool>,
+    pub metastore_id: Option<String>,
+    pub isolation_mode: Option<String>,
+    pub created_at: Option<i64>,
+    pub created_by: Option<String>,
+    pub updated_at: Option<i64>,
+    pub updated_by: Option<String>,
+    // excluded fields due to nesting
+    // aws_iam_role
+    // azure_managed_identity
+    // azure_service_principal
+    // databricks_gcp_service_account
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct ExternalLocationResponse {
+    pub external_locations: Option<Vec<ExternalLocation>>,
+}
+
+#[derive(Debug, Deserialize, Clone, FromRow)]
+pub struct ExternalLocation {
+    pub name: String,
+    pub url: String,
+    pub credential_name: Option<String>,
+    pub credential_id: Option<String>,
+    pub owner: Option<String>,
+    pub comment: Option<String>,
+    pub read_only: Option<bool>,
+    pub metastore_id: Option<String>,
+    pub isolation_mode: Option<String>,
+    pub created_at: Option<i64>,
+    pub created_by: Option<String>,
+    pub updated_at: Option<i64>,
+    pub updated_by: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Clone, FromRow)]
+pub struct MetastoreAssignment {
+    // not part of the api response, set from the client that fetched it
+    #[serde(default)]
+    pub workspace_name: String,
+    pub metastore_id: String,
+    pub workspace_id: Option<i64>,
+    pub default_catalog_name: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct TableCredentialResolution {
+    pub full_name: String,
+    pub storage_location: Option<String>,
+    pub external_location_name: Option<String>,
+    pub credential_name: Option<String>,
+}
@@ -1,8 +1,13 @@
 use reqwest::{Response, Error};
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
 use super::api_client::APIClient;
+use super::secret::Secret;
+use crate::sql::sql_client::{SqlClient, ListCatalogResultSet, ListSchemaResultSet, ListTableResultSet};
 use std::collections::HashMap;
-use std::env;
+
+// errors raised by calls that validate input before reaching the api
+pub type BoxError = Box<dyn std::error::Error + Send + Sync>;
 
 
 
@@ -15,7 +20,7 @@ pub struct Permissions {
 
 impl Permissions {
 
-    pub fn new(workspace_name: String, db_token: String ) -> Self {
+    pub fn new(workspace_name: String, db_token: Secret ) -> Self {
         let api_client: APIClient = APIClient{
             db_token: db_token,
             workspace_name: workspace_name
@@ -32,59 +37,173 @@ impl Permissions {
     ///
     /// * `user_name` - The username associated to the token
     /// * `user_token` - The token used for authentication against Unity Catalog
+    /// * `workspace_name` - The workspace the user authenticates against
     ///
     /// # Examples
     ///
     /// ```
-    /// let active_user: api::permissions::User = permissions_client.authenticate_user("ryan.chynoweth@databricks.com", &api_client.db_token).await?;
+    /// let active_user: User = permissions_client.authenticate_user(&config.principal, &config.db_token, &config.workspace_name).await?;
     /// ```
-    pub async fn authenticate_user(&self, user_name: &str, user_token: &str, workspace_name: &str) -> Result<AzureDataLakeGen2Options, Error> {
-        // need to add encryption and verification of user
-
-        // user_token will likely be required in the future as there will be a service token and a user token. 
-        let auth_url: String = format!("https://{}/api/2.0/preview/scim/v2/Me", &self.api_client.workspace_name);
-
-        let azure_storage_account_name: String = env::var("AZURE_STORAGE_ACCOUNT_NAME").expect("AZURE_STORAGE_ACCOUNT_NAME not set");
-        let azure_client_id: String = env::var("AZURE_CLIENT_ID").expect("AZURE_CLIENT_ID not set");
-        let azure_client_secret: String = env::var("AZURE_CLIENT_SECRET").expect("AZURE_CLIENT_SECRET not set");
-        let azure_tenant_id: String = env::var("AZURE_TENANT_ID").expect("AZURE_TENANT_ID not set");
-        // let workspace_name: String = env::var("WORKSPACE_NAME").expect("WORKSPACE_NAME not set");
-
-
-        let storage_options: AzureDataLakeGen2Options = AzureDataLakeGen2Options::new(
-            azure_storage_account_name,
-            azure_client_id,
-            azure_client_secret,
-            azure_tenant_id
-        );
-
-        // create a user client that is different from the other services? 
+    pub async fn authenticate_user(&self, user_name: &str, user_token: &Secret, workspace_name: &str) -> Result<User, BoxError> {
+        // authenticate with the user's own token rather than the service token
         let auth_client: APIClient = APIClient {
-            db_token: String::from(user_token),
+            db_token: user_token.clone(),
             workspace_name: String::from(workspace_name)
         };
+        let auth_url: String = format!("https://{}/api/2.0/preview/scim/v2/Me", &auth_client.workspace_name);
 
-        let response: Response = self.api_client.fetch(&auth_url).await?;
-        let status: bool = response.status().is_success();
+        // fail closed, every later permission check trusts the authenticated user name
+        let response: Response = auth_client.fetch(&auth_url).await?;
+        if !response.status().is_success() {
+            log::error!("Failed to authenticate user: {}", user_name);
+            return Err(format!("Authentication of {} failed with status {}", user_name, response.status()).into());
+        }
 
         let user: User = response.json().await?;
+        if user.user_name != user_name {
+            log::error!("Failed to authenticate user: {}, the token belongs to {}", user_name, user.user_name);
+            return Err(format!("The token does not belong to {}", user_name).into());
+        }
+        log::info!("User {} authentication was successful.", user.user_name);
 
-        if !status && user.user_name == user_name {
-            log::error!("Failed to authenticate user: {}", user.user_name);
-        } else {
-            log::info!("User {} authentication was successful.", user.user_name);
+        Ok(user)
+    }
+
+    /// Returns the groups a user belongs to, directly or through nested groups, service principals and groups return an empty list.
+    ///
+    /// # Arguments
+    ///
+    /// * `principal` - The user name of the user
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let groups: Vec<String> = permissions_client.get_principal_groups("ryan.chynoweth@databricks.com").await?;
+    /// ```
+    pub async fn get_principal_groups(&self, principal: &str) -> Result<Vec<String>, Error> {
+        if !principal.contains('@') {
+            return Ok(Vec::new());
         }
+        let url: String = scim_filter_url(&self.api_client.workspace_name, "Users", "userName", principal);
+        let response: Response = self.api_client.fetch(&url).await?.error_for_status()?;
+        let users: ScimUsersResponse = response.json().await?;
 
-        
-        Ok(storage_options)
+        // the user lists its direct groups, each group lists the groups it is a member of
+        let mut pending: Vec<GroupRef> = users.resources.unwrap_or_default().into_iter()
+            .flat_map(|user| user.groups.unwrap_or_default())
+            .collect();
+        let mut visited: Vec<String> = Vec::new();
+        let mut groups: Vec<String> = Vec::new();
+        while let Some(group) = pending.pop() {
+            let id: String = match group.value {
+                Some(id) => id,
+                None => continue,
+            };
+            if visited.contains(&id) {
+                continue;
+            }
+            visited.push(id.clone());
+            if let Some(name) = group.display {
+                groups.push(name);
+            }
+            let url: String = format!("https://{}/api/2.0/preview/scim/v2/Groups/{}", &self.api_client.workspace_name, percent_encode(&id));
+            let response: Response = self.api_client.fetch(&url).await?.error_for_status()?;
+            let parent: ScimGroup = response.json().await?;
+            pending.extend(parent.groups.unwrap_or_default());
+        }
+        groups.sort();
+        groups.dedup();
+        Ok(groups)
+    }
+
+    /// Returns whether a principal is a `user`, `service_principal` or `group`, None when the workspace does not know it.
+    ///
+    /// # Arguments
+    ///
+    /// * `principal` - The user name, application id or group name used in grants
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let principal_type: Option<String> = permissions_client.get_principal_type("analysts").await?;
+    /// ```
+    pub async fn get_principal_type(&self, principal: &str) -> Result<Option<String>, Error> {
+        // grants name users by user name, service principals by application id and groups by display name
+        let lookups: [(&str, &str, &str); 3] = [
+            ("Users", "userName", "user"),
+            ("ServicePrincipals", "applicationId", "service_principal"),
+            ("Groups", "displayName", "group"),
+        ];
+        for (resource, attribute, principal_type) in lookups {
+            let url: String = scim_filter_url(&self.api_client.workspace_name, resource, attribute, principal);
+            let response: Response = self.api_client.fetch(&url).await?.error_for_status()?;
+            let matched: ScimCountResponse = response.json().await?;
+            if matched.total_results.unwrap_or(0) > 0 {
+                return Ok(Some(principal_type.to_string()));
+            }
+        }
+        log::warn!("Principal {} was not found in workspace {}", principal, self.api_client.workspace_name);
+        Ok(None)
+    }
+
+    // sets the principal type of each assignment, types already looked up are taken from the cache
+    async fn resolve_principal_types(&self, assignments: &mut PrivilegeAssignmentsResponse, cache: &mut HashMap<String, Option<String>>) -> Result<(), Error> {
+        for assignment in assignments.privilege_assignments.iter_mut().flatten() {
+            let principal: String = match &assignment.principal {
+                Some(principal) => principal.clone(),
+                None => continue,
+            };
+            if !cache.contains_key(&principal) {
+                let principal_type: Option<String> = self.get_principal_type(&principal).await?;
+                cache.insert(principal.clone(), principal_type);
+            }
+            assignment.principal_type = cache.get(&principal).cloned().flatten();
+        }
+        Ok(())
+    }
+
+    /// Returns short lived storage credentials scoped to a single table, issued by Unity Catalog.
+    ///
+    /// # Arguments
+    ///
+    /// * `table_id` - The unity catalog id of the table
+    /// * `operation` - `READ` or `READ_WRITE`
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let creds: TemporaryTableCredentials = permissions_client.generate_temporary_table_credentials(&table.table_id, "READ").await?;
+    /// ```
+    pub async fn generate_temporary_table_credentials(&self, table_id: &str, operation: &str) -> Result<TemporaryTableCredentials, BoxError> {
+        // https://docs.databricks.com/api/workspace/temporarytablecredentials/generatetemporarytablecredentials
+        let url: String = format!("https://{}/api/2.1/unity-catalog/temporary-table-credentials", &self.api_client.workspace_name);
+        log::info!("Requesting {} credentials for table {}", operation, table_id);
+        let response: Response = self.api_client.post(&url, &json!({ "table_id": table_id, "operation": operation })).await?;
+        // permission denied and unsupported tables come back as an error body, not credentials
+        if !response.status().is_success() {
+            let status = response.status();
+            let resp_text: String = response.text().await?;
+            return Err(format!("Failed to get {} credentials for table {}: {} - {}", operation, table_id, status, resp_text).into());
+        }
+        let credentials: TemporaryTableCredentials = response.json().await?;
+        Ok(credentials)
     }
 
     // /api/2.1/unity-catalog/permissions/{securable_type}/{full_name}
     pub async fn fetch_permissions(&self, securable_type: SecurableType, full_name: &str, principal: &str) -> Result<PrivilegeAssignmentsResponse, Error> {
 
-        let securable_type_str = securable_type.to_string();
+        let securable_type_str = securable_type.permissions_type();
+        let mut privileges: PrivilegeAssignmentsResponse = PrivilegeAssignmentsResponse::new();
 
-        // focusing just on tables for now
+        // securables outside of a catalog have no parent objects to collect permissions from
+        if !securable_type.in_catalog() {
+            let obj_auth_url: String = format!("https://{}/api/2.1/unity-catalog/permissions/{}/{}?principal={}", &self.api_client.workspace_name, &securable_type_str, full_name, principal);
+            log::info!("Getting Object Permissions - {}", obj_auth_url);
+            let obj_response: Response = self.api_client.fetch(&obj_auth_url).await?;
+            let obj_perms: PrivilegeAssignmentsResponse = obj_response.json().await?;
+            privileges.add_assignment(obj_perms, full_name, securable_type);
+            return Ok(privileges);
+        }
 
         // split full name and make 3 different api calls since permissions can be delagated 
         let name_parts: Vec<&str> = full_name.split('.').collect();
@@ -94,10 +213,6 @@ impl Permissions {
             _ => "".to_string(), // handle case where parts are missing
         };        
 
-
-
-        let mut privileges: PrivilegeAssignmentsResponse = PrivilegeAssignmentsResponse::new();
-
         // make calls for each part of the object name to collect permissions 
         if !name_parts.get(0).is_none() {
             let catalog_auth_url: String = format!("https://{}/api/2.1/unity-catalog/permissions/{}/{}?principal={}", &self.api_client.workspace_name, "catalog", catalog_name, principal);
@@ -118,34 +233,182 @@ impl Permissions {
             log::info!("Getting Object Permissions - {}", obj_auth_url);
             let obj_response: Response = self.api_client.fetch(&obj_auth_url).await?;
             let obj_perms: PrivilegeAssignmentsResponse = obj_response.json().await?;
-            privileges.add_assignment(obj_perms, &full_name, SecurableType::Table);
+            privileges.add_assignment(obj_perms, full_name, securable_type);
         }      
         
         Ok(privileges)
     }
 
     
+    /// Returns every privilege assignment made directly on a securable, across all principals.
+    ///
+    /// # Arguments
+    ///
+    /// * `securable_type` - The type of the securable
+    /// * `full_name` - The fully qualified name of the securable
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let grants: PrivilegeAssignmentsResponse = permissions_client.get_assignments(SecurableType::Schema, "rac_demo_catalog.retail_pos").await?;
+    /// ```
+    pub async fn get_assignments(&self, securable_type: SecurableType, full_name: &str) -> Result<PrivilegeAssignmentsResponse, Error> {
+        let url: String = format!("https://{}/api/2.1/unity-catalog/permissions/{}/{}", &self.api_client.workspace_name, securable_type.permissions_type(), full_name);
+        log::info!("Getting Permissions - {}", url);
+        let response: Response = self.api_client.fetch(&url).await?;
+        let object_perms: PrivilegeAssignmentsResponse = response.json().await?;
+
+        let mut privileges: PrivilegeAssignmentsResponse = PrivilegeAssignmentsResponse::new();
+        privileges.add_assignment(object_perms, full_name, securable_type);
+        Ok(privileges)
+    }
+
+    /// Grants privileges on a securable to a principal.
+    ///
+    /// # Arguments
+    ///
+    /// * `securable_type` - The type of the securable
+    /// * `full_name` - The fully qualified name of the securable
+    /// * `principal` - The user, group or service principal receiving the privileges
+    /// * `privileges` - The privileges to grant i.e. `SELECT`
+    /// * `dry_run` - When true nothing is changed and the resulting assignments are returned
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let grants = permissions_client.grant(SecurableType::Table, "my_catalog.my_schema.my_table", "analysts", vec![Privilege::Select], true).await?;
+    /// ```
+    pub async fn grant(&self, securable_type: SecurableType, full_name: &str, principal: &str, privileges: Vec<Privilege>, dry_run: bool) -> Result<PrivilegeAssignmentsResponse, BoxError> {
+        let change: PermissionsChange = PermissionsChange::add(principal, privileges);
+        self.update_permissions(securable_type, full_name, vec![change], dry_run).await
+    }
+
+    /// Revokes privileges on a securable from a principal.
+    ///
+    /// # Arguments
+    ///
+    /// * `securable_type` - The type of the securable
+    /// * `full_name` - The fully qualified name of the securable
+    /// * `principal` - The user, group or service principal losing the privileges
+    /// * `privileges` - The privileges to revoke i.e. `SELECT`
+    /// * `dry_run` - When true nothing is changed and the resulting assignments are returned
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let grants = permissions_client.revoke(SecurableType::Table, "my_catalog.my_schema.my_table", "analysts", vec![Privilege::Modify], false).await?;
+    /// ```
+    pub async fn revoke(&self, securable_type: SecurableType, full_name: &str, principal: &str, privileges: Vec<Privilege>, dry_run: bool) -> Result<PrivilegeAssignmentsResponse, BoxError> {
+        let change: PermissionsChange = PermissionsChange::remove(principal, privileges);
+        self.update_permissions(securable_type, full_name, vec![change], dry_run).await
+    }
+
+    // PATCH /api/2.1/unity-catalog/permissions/{securable_type}/{full_name}
+    // privileges are validated against the securable type before any request is made
+    pub async fn update_permissions(&self, securable_type: SecurableType, full_name: &str, changes: Vec<PermissionsChange>, dry_run: bool) -> Result<PrivilegeAssignmentsResponse, BoxError> {
+        for change in &changes {
+            securable_type.validate_privileges(&change.add)?;
+            securable_type.validate_privileges(&change.remove)?;
+        }
+
+        if dry_run {
+            let mut privileges: PrivilegeAssignmentsResponse = self.get_assignments(securable_type.clone(), full_name).await?;
+            privileges.apply_changes(&changes);
+            log::info!("Dry run - resulting assignments on {} {}:", securable_type.to_string(), full_name);
+            if let Some(assigns) = &privileges.privilege_assignments {
+                for a in assigns {
+                    log::info!("  {} - {:?}", a.principal.clone().unwrap_or_default(), a.privileges.clone().unwrap_or_default());
+                }
+            }
+            return Ok(privileges);
+        }
+
+        let url: String = format!("https://{}/api/2.1/unity-catalog/permissions/{}/{}", &self.api_client.workspace_name, securable_type.permissions_type(), full_name);
+        log::info!("Updating Permissions - {}", url);
+        let response: Response = self.api_client.patch(&url, &json!({ "changes": changes })).await?;
+        if !response.status().is_success() {
+            let status = response.status();
+            let resp_text: String = response.text().await?;
+            return Err(format!("Failed to update permissions on {}: {} - {}", full_name, status, resp_text).into());
+        }
+
+        let updated: PrivilegeAssignmentsResponse = response.json().await?;
+        let mut privileges: PrivilegeAssignmentsResponse = PrivilegeAssignmentsResponse::new();
+        privileges.add_assignment(updated, full_name, securable_type);
+        Ok(privileges)
+    }
+
+    /// Crawls every catalog, schema and table in the local metastore mirror and stores all of their privilege assignments.
+    /// The metastore mirror should be refreshed first as it provides the list of objects.
+    ///
+    /// # Arguments
+    ///
+    /// * `sql_client` - The client for the local database
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// permissions_client.refresh_all_permissions(&sql_client).await?;
+    /// ```
+    pub async fn refresh_all_permissions(&self, sql_client: &SqlClient) -> Result<(), BoxError> {
+        let catalogs: Vec<ListCatalogResultSet> = sql_client.list_catalogs(Some(&self.api_client.workspace_name), None).await?;
+        for catalog in catalogs {
+            self.refresh_permissions(sql_client, &catalog.name).await?;
+        }
+        Ok(())
+    }
+
+    pub async fn refresh_permissions(&self, sql_client: &SqlClient, catalog_name: &str) -> Result<(), BoxError> {
+        log::info!("Getting Permissions for Catalog {}.", catalog_name);
+        let workspace_name: &str = &self.api_client.workspace_name;
+        // grants are stored under the metastore of the mirrored catalog
+        let metastore_id: String = sql_client.list_catalogs(Some(workspace_name), Some(catalog_name)).await?
+            .into_iter()
+            .find(|c| c.name == catalog_name)
+            .map(|c| c.metastore_id)
+            .ok_or_else(|| format!("Catalog {} of {} is not mirrored, refresh the catalogs first.", catalog_name, workspace_name))?;
+        let mut principal_types: HashMap<String, Option<String>> = HashMap::new();
+        let mut catalog_perms: PrivilegeAssignmentsResponse = self.get_assignments(SecurableType::Catalog, catalog_name).await?;
+        self.resolve_principal_types(&mut catalog_perms, &mut principal_types).await?;
+        sql_client.write_privilege_assignments(workspace_name, &metastore_id, "catalog", catalog_name, catalog_perms).await?;
+
+        let schemas: Vec<ListSchemaResultSet> = sql_client.list_schemas(Some(&self.api_client.workspace_name), Some(catalog_name), None).await?;
+        for schema in schemas {
+            let schema_name: String = format!("{}.{}", schema.catalog_name, schema.name);
+            let mut schema_perms: PrivilegeAssignmentsResponse = self.get_assignments(SecurableType::Schema, &schema_name).await?;
+            self.resolve_principal_types(&mut schema_perms, &mut principal_types).await?;
+            sql_client.write_privilege_assignments(workspace_name, &metastore_id, "schema", &schema_name, schema_perms).await?;
+
+            let tables: Vec<ListTableResultSet> = sql_client.list_tables(Some(&self.api_client.workspace_name), Some(catalog_name), Some(&schema.name), None).await?;
+            for table in tables {
+                let table_name: String = format!("{}.{}.{}", table.catalog_name, table.schema_name, table.name);
+                let mut table_perms: PrivilegeAssignmentsResponse = self.get_assignments(SecurableType::Table, &table_name).await?;
+                self.resolve_principal_types(&mut table_perms, &mut principal_types).await?;
+                sql_client.write_privilege_assignments(workspace_name, &metastore_id, "table", &table_name, table_perms).await?;
+            }
+        }
+        Ok(())
+    }
+
     pub async fn get_object_owner(&self, securable_type: SecurableType, full_name: &str) -> Result<ObjectOwnerResponse, Error>{
         // https://docs.databricks.com/en/data-governance/unity-catalog/manage-privileges/ownership.html
         // owners of objects automatically have full control of object 
 
         log::info!("Checking ownership on {}: {}", securable_type.to_string(), full_name);
 
-        let url: String = format!("https://{}/api/2.1/unity-catalog/{}s/{}", &self.api_client.workspace_name, securable_type.to_string(), full_name);
+        let url: String = format!("https://{}/api/2.1/unity-catalog/{}/{}", &self.api_client.workspace_name, securable_type.api_collection(), full_name);
         let response: Response = self.api_client.fetch(&url).await?;
-        let status: bool = response.status().is_success();
-
-        let owner_response: ObjectOwnerResponse = response.json().await?;
-
-
-        if !status {
+        if !response.status().is_success() {
             log::error!("Failed to get owner of object - {}", full_name);
-        } 
+        }
+
+        // a missing or forbidden object is an error, the body is not an owner response
+        let owner_response: ObjectOwnerResponse = response.error_for_status()?.json().await?;
 
         Ok(owner_response)
     }   
 
-    async fn check_permissions(&self, securable_type: SecurableType, full_name: &str, principal: &str, permissions: Vec<&str>) -> Result<bool, Error> {
+    async fn check_permissions(&self, securable_type: SecurableType, full_name: &str, principal: &str, permissions: Vec<Privilege>) -> Result<bool, Error> {
         let mut perm_check: bool = false; // deny by default
         let object_permissions: PrivilegeAssignmentsResponse = self.fetch_permissions(securable_type.clone(), full_name, principal).await?;
 
@@ -158,13 +421,13 @@ impl Permissions {
         };
 
         // if they are an owner of the object or one of the parent objects then we return TRUE 
-        if self.get_object_owner(securable_type.clone(), full_name).await.unwrap().owner == principal { 
+        if self.get_object_owner(securable_type.clone(), full_name).await?.owner == principal { 
             log::info!("Princpal {} is an owner of {}. ", principal, full_name);
             perm_check = true; 
-        } else if !name_parts.get(1).is_none() && self.get_object_owner(SecurableType::Schema, &schema_name).await.unwrap().owner == principal { // if princpal is the owner of the schema then return True 
+        } else if securable_type.in_catalog() && name_parts.get(1).is_some() && self.get_object_owner(SecurableType::Schema, &schema_name).await?.owner == principal { // if princpal is the owner of the schema then return True 
             log::info!("Princpal {} is an owner of {}. ", principal, schema_name);
             perm_check = true;
-        } else if !name_parts.get(0).is_none() && self.get_object_owner(SecurableType::Catalog, &catalog_name).await.unwrap().owner == principal { // if princpal is the owner of the catalog then return True 
+        } else if securable_type.in_catalog() && !name_parts.is_empty() && self.get_object_owner(SecurableType::Catalog, catalog_name).await?.owner == principal { // if princpal is the owner of the catalog then return True 
             log::info!("Princpal {} is an owner of {}. ", principal, catalog_name);
             perm_check = true;
         } 
@@ -178,7 +441,7 @@ impl Permissions {
                     if let Some(pp) = s.privileges {
                         for value in pp {
                             // if the value is in the read_list vec then return TRUE
-                            if permissions.contains(&value.as_str()) {
+                            if permissions.contains(&value) {
                                 log::info!("Principal {} has {} permissions on {}.", p, value, s.object_name);
                                 perm_check = true;
                             }
@@ -190,10 +453,23 @@ impl Permissions {
         Ok(perm_check)
     }
 
-    // /api/2.1/unity-catalog/permissions/{securable_type}/{full_name}
-    pub async fn can_read(&self, full_name: &str, principal: &str) -> Result<bool, Error> {
-        let readable_permissions = vec!["SELECT", "ALL_PRIVILEGES"];
-        let securable_type: SecurableType = SecurableType::Table;
+    /// Checks whether a principal is allowed to perform an action on a securable.
+    /// Owners of the object or any parent object are always allowed, otherwise a privilege granting the action is required.
+    ///
+    /// # Arguments
+    ///
+    /// * `action` - The action being performed i.e. `Action::Read`
+    /// * `securable_type` - The type of the securable
+    /// * `full_name` - The fully qualified name of the securable
+    /// * `principal` - The principal performing the action
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let readable: bool = permissions_client.can(Action::Read, SecurableType::Table, "my_catalog.my_schema.my_table", principal).await?;
+    /// ```
+    pub async fn can(&self, action: Action, securable_type: SecurableType, full_name: &str, principal: &str) -> Result<bool, Error> {
+        let required: Vec<Privilege> = action.privileges(&securable_type);
 
         // split full name and make 3 different api calls since permissions can be delagated 
         let name_parts: Vec<&str> = full_name.split('.').collect();
@@ -202,31 +478,19 @@ impl Permissions {
             (Some(part1), Some(part2)) => format!("{}.{}", part1, part2),
             _ => "".to_string(), // handle case where parts are missing
         };
-        log::info!("Checking if {} can read the following objects: {} | {} | {}", principal, catalog_name, schema_name, full_name);
+        log::info!("Checking if {} can {} the following objects: {} | {} | {}", principal, action, catalog_name, schema_name, full_name);
 
-        let readable: bool = self.check_permissions(securable_type, full_name, principal, readable_permissions).await?; // deny by default
+        let allowed: bool = self.check_permissions(securable_type, full_name, principal, required).await?; // deny by default
 
-        Ok(readable)
+        Ok(allowed)
     }
 
+    pub async fn can_read(&self, full_name: &str, principal: &str) -> Result<bool, Error> {
+        self.can(Action::Read, SecurableType::Table, full_name, principal).await
+    }
 
     pub async fn can_write(&self, full_name: &str, principal: &str) -> Result<bool, Error> {
-        let writable_permissions: Vec<&str> = vec!["MODIFY", "ALL_PRIVILEGES"];
-        let securable_type: SecurableType = SecurableType::Table;
-
-
-        // split full name and make 3 different api calls since permissions can be delagated 
-        let name_parts: Vec<&str> = full_name.split('.').collect();
-        let catalog_name = name_parts.get(0).unwrap().trim_matches('"'); // always expect a catalog        
-        let schema_name = match (name_parts.get(0), name_parts.get(1)) { // may not always be a schema
-            (Some(part1), Some(part2)) => format!("{}.{}", part1, part2),
-            _ => "".to_string(), // handle case where parts are missing
-        };
-        log::info!("Checking if {} can read the following objects: {} | {} | {}", principal, catalog_name, schema_name, full_name);
-
-        let writable: bool = self.check_permissions(securable_type, full_name, principal, writable_permissions).await?; // deny by default
-
-        Ok(writable)
+        self.can(Action::Write, SecurableType::Table, full_name, principal).await
     }
 }
 
@@ -258,6 +522,64 @@ impl PrivilegeAssignmentsResponse {
             }
         }
     }
+
+    // Method to apply add/remove changes locally, used to preview a permissions update
+    pub fn apply_changes(&mut self, changes: &[PermissionsChange]) {
+        let assigns = self.privilege_assignments.get_or_insert_with(Vec::new);
+        for change in changes {
+            let object_name: String = assigns.first().map(|a| a.object_name.clone()).unwrap_or_default();
+            let object_type: Option<SecurableType> = assigns.first().and_then(|a| a.object_type.clone());
+            let position = assigns.iter().position(|a| a.principal.as_deref() == Some(change.principal.as_str()));
+            let assignment: &mut PrivilegeAssignment = match position {
+                Some(i) => &mut assigns[i],
+                None => {
+                    assigns.push(PrivilegeAssignment {
+                        object_name,
+                        object_type,
+                        principal: Some(change.principal.clone()),
+                        principal_type: None,
+                        privileges: Some(Vec::new()),
+                    });
+                    assigns.last_mut().unwrap()
+                }
+            };
+
+            let privs = assignment.privileges.get_or_insert_with(Vec::new);
+            for p in &change.add {
+                if !privs.contains(p) {
+                    privs.push(p.clone());
+                }
+            }
+            privs.retain(|p| !change.remove.contains(p));
+        }
+        // principals left without any privileges no longer have an assignment
+        assigns.retain(|a| a.privileges.as_ref().is_some_and(|p| !p.is_empty()));
+    }
+}
+
+// a single entry in the `changes` list of a permissions update
+#[derive(Debug, Serialize, Clone)]
+pub struct PermissionsChange {
+    pub principal: String,
+    pub add: Vec<Privilege>,
+    pub remove: Vec<Privilege>,
+}
+impl PermissionsChange {
+    pub fn add(principal: &str, privileges: Vec<Privilege>) -> Self {
+        PermissionsChange {
+            principal: principal.to_string(),
+            add: privileges,
+            remove: Vec::new(),
+        }
+    }
+
+    pub fn remove(principal: &str, privileges: Vec<Privilege>) -> Self {
+        PermissionsChange {
+            principal: principal.to_string(),
+            add: Vec::new(),
+            remove: privileges,
+        }
+    }
 }
 
 // struct to old ownership information 
@@ -268,6 +590,22 @@ pub struct ObjectOwnerResponse {
 }
 
 
+// a scim list url filtered on an attribute equal to the value, the value is quoted for scim and percent encoded for the url
+// https://datatracker.ietf.org/doc/html/rfc7644#section-3.4.2.2
+fn scim_filter_url(workspace_name: &str, resource: &str, attribute: &str, value: &str) -> String {
+    let quoted: String = value.replace('\\', "\\\\").replace('"', "\\\"");
+    let filter: String = format!("{} eq \"{}\"", attribute, quoted);
+    format!("https://{}/api/2.0/preview/scim/v2/{}?filter={}", workspace_name, resource, percent_encode(&filter))
+}
+
+// percent encodes everything but unreserved characters, for use in a url path segment or query value
+fn percent_encode(value: &str) -> String {
+    value.bytes().map(|b| match b {
+        b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => (b as char).to_string(),
+        _ => format!("%{:02X}", b),
+    }).collect()
+}
+
 // objects for permissions 
 #[derive(Debug, Deserialize, Clone)]
 pub struct PrivilegeAssignment {
@@ -275,7 +613,9 @@ pub struct PrivilegeAssignment {
     pub object_name: String,
     pub object_type: Option<SecurableType>,
     pub principal: Option<String>,
-    pub privileges: Option<Vec<String>>,
+    #[serde(skip)]
+    pub principal_type: Option<String>, // user, group or service_principal, resolved through scim
+    pub privileges: Option<Vec<Privilege>>,
 }
 
 // used to authenticate users 
@@ -286,10 +626,37 @@ pub struct User {
     pub user_name: String, 
     pub display_name: String,
     pub active: bool,
+    pub groups: Option<Vec<GroupRef>>,
 }
 
+#[derive(Debug, Deserialize, Clone)]
+pub struct GroupRef {
+    pub display: Option<String>,
+    pub value: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct ScimUsersResponse {
+    #[serde(rename = "Resources")]
+    pub resources: Option<Vec<User>>,
+}
 
+// the groups a group is a member of
 #[derive(Debug, Deserialize, Clone)]
+pub struct ScimGroup {
+    pub groups: Option<Vec<GroupRef>>,
+}
+
+// the number of resources a scim list request matched
+#[derive(Debug, Deserialize, Clone)]
+pub struct ScimCountResponse {
+    #[serde(rename = "totalResults")]
+    pub total_results: Option<i64>,
+}
+
+
+#[derive(Debug, Deserialize, Clone)]
+#[serde(rename_all = "snake_case")]
 pub enum SecurableType {
     // https://docs.databricks.com/en/data-governance/unity-catalog/manage-privileges/privileges.html
     Catalog, // metastore ownership
@@ -304,6 +671,70 @@ pub enum SecurableType {
     Metastore, // account ownership - we can likely disregard as we are only working with single metastores
     Volume, // schema ownership 
     Connection, // federation - metastore ownership
+    RegisteredModel, // schema ownership - shares the function securable type in the permissions api
+}
+impl SecurableType {
+    // privileges that can be granted on each securable type
+    // https://docs.databricks.com/en/data-governance/unity-catalog/manage-privileges/privileges.html
+    pub fn privileges(&self) -> Vec<Privilege> {
+        match self {
+            SecurableType::Catalog => vec![Privilege::AllPrivileges, Privilege::ApplyTag, Privilege::Browse, Privilege::CreateFunction, Privilege::CreateMaterializedView, Privilege::CreateModel, Privilege::CreateSchema, Privilege::CreateTable, Privilege::CreateVolume, Privilege::Execute, Privilege::Modify, Privilege::ReadVolume, Privilege::Refresh, Privilege::Select, Privilege::UseCatalog, Privilege::UseSchema, Privilege::WriteVolume],
+            SecurableType::Schema => vec![Privilege::AllPrivileges, Privilege::ApplyTag, Privilege::CreateFunction, Privilege::CreateMaterializedView, Privilege::CreateModel, Privilege::CreateTable, Privilege::CreateVolume, Privilege::Execute, Privilege::Modify, Privilege::ReadVolume, Privilege::Refresh, Privilege::Select, Privilege::UseSchema, Privilege::WriteVolume],
+            SecurableType::Table => vec![Privilege::AllPrivileges, Privilege::ApplyTag, Privilege::Modify, Privilege::Refresh, Privilege::Select],
+            SecurableType::StorageCredential => vec![Privilege::AllPrivileges, Privilege::CreateExternalLocation, Privilege::CreateExternalTable, Privilege::ReadFiles, Privilege::WriteFiles],
+            SecurableType::ExternalLocation => vec![Privilege::AllPrivileges, Privilege::Browse, Privilege::CreateExternalTable, Privilege::CreateExternalVolume, Privilege::CreateManagedStorage, Privilege::ReadFiles, Privilege::WriteFiles],
+            SecurableType::Function => vec![Privilege::AllPrivileges, Privilege::ApplyTag, Privilege::Execute],
+            SecurableType::Share => vec![Privilege::Select],
+            SecurableType::Provider => vec![],
+            SecurableType::Recipient => vec![],
+            SecurableType::Metastore => vec![Privilege::CreateCatalog, Privilege::CreateConnection, Privilege::CreateExternalLocation, Privilege::CreateProvider, Privilege::CreateRecipient, Privilege::CreateShare, Privilege::CreateStorageCredential, Privilege::ManageAllowlist, Privilege::SetSharePermission, Privilege::UseMarketplaceAssets, Privilege::UseProvider, Privilege::UseRecipient, Privilege::UseShare],
+            SecurableType::Volume => vec![Privilege::AllPrivileges, Privilege::ApplyTag, Privilege::ReadVolume, Privilege::WriteVolume],
+            SecurableType::Connection => vec![Privilege::AllPrivileges, Privilege::CreateForeignCatalog, Privilege::UseConnection],
+            SecurableType::RegisteredModel => vec![Privilege::AllPrivileges, Privilege::ApplyTag, Privilege::Execute],
+        }
+    }
+
+    // catalogs and the objects beneath them, these inherit privileges and ownership from their parents
+    pub fn in_catalog(&self) -> bool {
+        matches!(self, SecurableType::Catalog | SecurableType::Schema | SecurableType::Table | SecurableType::Volume | SecurableType::Function | SecurableType::RegisteredModel)
+    }
+
+    // securable type used in /api/2.1/unity-catalog/permissions/{securable_type}/{full_name}
+    pub fn permissions_type(&self) -> String {
+        match self {
+            SecurableType::RegisteredModel => "function".to_string(),
+            _ => self.to_string(),
+        }
+    }
+
+    // collection used to get the object itself i.e. /api/2.1/unity-catalog/{collection}/{full_name}
+    pub fn api_collection(&self) -> String {
+        match self {
+            SecurableType::Catalog => "catalogs".to_string(),
+            SecurableType::Schema => "schemas".to_string(),
+            SecurableType::Table => "tables".to_string(),
+            SecurableType::StorageCredential => "storage-credentials".to_string(),
+            SecurableType::ExternalLocation => "external-locations".to_string(),
+            SecurableType::Function => "functions".to_string(),
+            SecurableType::Share => "shares".to_string(),
+            SecurableType::Provider => "providers".to_string(),
+            SecurableType::Recipient => "recipients".to_string(),
+            SecurableType::Metastore => "metastores".to_string(),
+            SecurableType::Volume => "volumes".to_string(),
+            SecurableType::Connection => "connections".to_string(),
+            SecurableType::RegisteredModel => "models".to_string(),
+        }
+    }
+
+    pub fn validate_privileges(&self, privileges: &[Privilege]) -> Result<(), String> {
+        let valid: Vec<Privilege> = self.privileges();
+        let invalid: Vec<String> = privileges.iter().filter(|p| !valid.contains(p)).map(|p| p.to_string()).collect();
+        if !invalid.is_empty() {
+            log::error!("Invalid privileges for {}: {:?}", self.to_string(), invalid);
+            return Err(format!("Privileges {:?} cannot be granted on a {}", invalid, self.to_string()));
+        }
+        Ok(())
+    }
 }
 impl std::str::FromStr for SecurableType {
     type Err = ();
@@ -322,6 +753,7 @@ impl std::str::FromStr for SecurableType {
             "metastore" => Ok(SecurableType::Metastore),
             "volume" => Ok(SecurableType::Volume),
             "connection" => Ok(SecurableType::Connection),
+            "registered_model" => Ok(SecurableType::RegisteredModel),
             _ => Err(()),
         }
     }
@@ -341,6 +773,7 @@ impl ToString for SecurableType {
             SecurableType::Metastore => "metastore".to_string(),
             SecurableType::Volume => "volume".to_string(),
             SecurableType::Connection => "connection".to_string(),
+            SecurableType::RegisteredModel => "registered_model".to_string(),
         }
     }
 }
@@ -348,32 +781,338 @@ impl ToString for SecurableType {
 
 
 
-// https://delta-io.github.io/delta-rs/usage/loading-table/
-// https://docs.rs/object_store/latest/object_store/azure/enum.AzureConfigKey.html#variants
-#[derive(Debug, Clone, Deserialize)]
-pub struct AzureDataLakeGen2Options {
-    azure_storage_account_name: String, 
-    azure_client_id: String,
-    azure_client_secret: String,
-    azure_tenant_id: String,
+// unity catalog privileges, privileges this crate does not know about are kept as `Other`
+// https://docs.databricks.com/en/data-governance/unity-catalog/manage-privileges/privileges.html
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Hash)]
+#[serde(from = "String", into = "String")]
+pub enum Privilege {
+    AllPrivileges,
+    ApplyTag,
+    Browse,
+    CreateCatalog,
+    CreateConnection,
+    CreateExternalLocation,
+    CreateExternalTable,
+    CreateExternalVolume,
+    CreateForeignCatalog,
+    CreateFunction,
+    CreateManagedStorage,
+    CreateMaterializedView,
+    CreateModel,
+    CreateProvider,
+    CreateRecipient,
+    CreateSchema,
+    CreateShare,
+    CreateStorageCredential,
+    CreateTable,
+    CreateVolume,
+    Execute,
+    ManageAllowlist,
+    Modify,
+    ReadFiles,
+    ReadVolume,
+    Refresh,
+    Select,
+    SetSharePermission,
+    UseCatalog,
+    UseConnection,
+    UseMarketplaceAssets,
+    UseProvider,
+    UseRecipient,
+    UseSchema,
+    UseShare,
+    WriteFiles,
+    WriteVolume,
+    Other(String),
 }
-impl AzureDataLakeGen2Options {
-    pub fn new(azure_storage_account_name: String, azure_client_id: String, azure_client_secret: String, azure_tenant_id: String ) -> Self {
-        let options: AzureDataLakeGen2Options = AzureDataLakeGen2Options {
-            azure_storage_account_name,
-            azure_client_id,
-            azure_client_secret,
-            azure_tenant_id
+impl std::str::FromStr for Privilege {
+    type Err = ();
+
+    fn from_str(input: &str) -> Result<Privilege, Self::Err> {
+        match input {
+            "ALL_PRIVILEGES" => Ok(Privilege::AllPrivileges),
+            "APPLY_TAG" => Ok(Privilege::ApplyTag),
+            "BROWSE" => Ok(Privilege::Browse),
+            "CREATE_CATALOG" => Ok(Privilege::CreateCatalog),
+            "CREATE_CONNECTION" => Ok(Privilege::CreateConnection),
+            "CREATE_EXTERNAL_LOCATION" => Ok(Privilege::CreateExternalLocation),
+            "CREATE_EXTERNAL_TABLE" => Ok(Privilege::CreateExternalTable),
+            "CREATE_EXTERNAL_VOLUME" => Ok(Privilege::CreateExternalVolume),
+            "CREATE_FOREIGN_CATALOG" => Ok(Privilege::CreateForeignCatalog),
+            "CREATE_FUNCTION" => Ok(Privilege::CreateFunction),
+            "CREATE_MANAGED_STORAGE" => Ok(Privilege::CreateManagedStorage),
+            "CREATE_MATERIALIZED_VIEW" => Ok(Privilege::CreateMaterializedView),
+            "CREATE_MODEL" => Ok(Privilege::CreateModel),
+            "CREATE_PROVIDER" => Ok(Privilege::CreateProvider),
+            "CREATE_RECIPIENT" => Ok(Privilege::CreateRecipient),
+            "CREATE_SCHEMA" => Ok(Privilege::CreateSchema),
+            "CREATE_SHARE" => Ok(Privilege::CreateShare),
+            "CREATE_STORAGE_CREDENTIAL" => Ok(Privilege::CreateStorageCredential),
+            "CREATE_TABLE" => Ok(Privilege::CreateTable),
+            "CREATE_VOLUME" => Ok(Privilege::CreateVolume),
+            "EXECUTE" => Ok(Privilege::Execute),
+            "MANAGE_ALLOWLIST" => Ok(Privilege::ManageAllowlist),
+            "MODIFY" => Ok(Privilege::Modify),
+            "READ_FILES" => Ok(Privilege::ReadFiles),
+            "READ_VOLUME" => Ok(Privilege::ReadVolume),
+            "REFRESH" => Ok(Privilege::Refresh),
+            "SELECT" => Ok(Privilege::Select),
+            "SET_SHARE_PERMISSION" => Ok(Privilege::SetSharePermission),
+            "USE_CATALOG" => Ok(Privilege::UseCatalog),
+            "USE_CONNECTION" => Ok(Privilege::UseConnection),
+            "USE_MARKETPLACE_ASSETS" => Ok(Privilege::UseMarketplaceAssets),
+            "USE_PROVIDER" => Ok(Privilege::UseProvider),
+            "USE_RECIPIENT" => Ok(Privilege::UseRecipient),
+            "USE_SCHEMA" => Ok(Privilege::UseSchema),
+            "USE_SHARE" => Ok(Privilege::UseShare),
+            "WRITE_FILES" => Ok(Privilege::WriteFiles),
+            "WRITE_VOLUME" => Ok(Privilege::WriteVolume),
+            _ => Err(()),
+        }
+    }
+}
+impl std::fmt::Display for Privilege {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name: &str = match self {
+            Privilege::AllPrivileges => "ALL_PRIVILEGES",
+            Privilege::ApplyTag => "APPLY_TAG",
+            Privilege::Browse => "BROWSE",
+            Privilege::CreateCatalog => "CREATE_CATALOG",
+            Privilege::CreateConnection => "CREATE_CONNECTION",
+            Privilege::CreateExternalLocation => "CREATE_EXTERNAL_LOCATION",
+            Privilege::CreateExternalTable => "CREATE_EXTERNAL_TABLE",
+            Privilege::CreateExternalVolume => "CREATE_EXTERNAL_VOLUME",
+            Privilege::CreateForeignCatalog => "CREATE_FOREIGN_CATALOG",
+            Privilege::CreateFunction => "CREATE_FUNCTION",
+            Privilege::CreateManagedStorage => "CREATE_MANAGED_STORAGE",
+            Privilege::CreateMaterializedView => "CREATE_MATERIALIZED_VIEW",
+            Privilege::CreateModel => "CREATE_MODEL",
+            Privilege::CreateProvider => "CREATE_PROVIDER",
+            Privilege::CreateRecipient => "CREATE_RECIPIENT",
+            Privilege::CreateSchema => "CREATE_SCHEMA",
+            Privilege::CreateShare => "CREATE_SHARE",
+            Privilege::CreateStorageCredential => "CREATE_STORAGE_CREDENTIAL",
+            Privilege::CreateTable => "CREATE_TABLE",
+            Privilege::CreateVolume => "CREATE_VOLUME",
+            Privilege::Execute => "EXECUTE",
+            Privilege::ManageAllowlist => "MANAGE_ALLOWLIST",
+            Privilege::Modify => "MODIFY",
+            Privilege::ReadFiles => "READ_FILES",
+            Privilege::ReadVolume => "READ_VOLUME",
+            Privilege::Refresh => "REFRESH",
+            Privilege::Select => "SELECT",
+            Privilege::SetSharePermission => "SET_SHARE_PERMISSION",
+            Privilege::UseCatalog => "USE_CATALOG",
+            Privilege::UseConnection => "USE_CONNECTION",
+            Privilege::UseMarketplaceAssets => "USE_MARKETPLACE_ASSETS",
+            Privilege::UseProvider => "USE_PROVIDER",
+            Privilege::UseRecipient => "USE_RECIPIENT",
+            Privilege::UseSchema => "USE_SCHEMA",
+            Privilege::UseShare => "USE_SHARE",
+            Privilege::WriteFiles => "WRITE_FILES",
+            Privilege::WriteVolume => "WRITE_VOLUME",
+            Privilege::Other(p) => p,
         };
-        options
+        write!(f, "{}", name)
     }
+}
+impl From<String> for Privilege {
+    fn from(input: String) -> Self {
+        input.parse().unwrap_or(Privilege::Other(input))
+    }
+}
+impl From<Privilege> for String {
+    fn from(privilege: Privilege) -> Self {
+        privilege.to_string()
+    }
+}
+
+
+// actions a principal can take on a securable, each is satisfied by a set of privileges
+#[derive(Debug, Clone, PartialEq)]
+pub enum Action {
+    Read,
+    Write,
+    Execute,
+}
+impl Action {
+    // privileges on the securable, or inherited from its parents, that allow the action
+    // an empty list means only owners can take the action
+    pub fn privileges(&self, securable_type: &SecurableType) -> Vec<Privilege> {
+        let required: Vec<Privilege> = match (self, securable_type) {
+            (Action::Read, SecurableType::Catalog | SecurableType::Schema | SecurableType::Table | SecurableType::Share) => vec![Privilege::Select],
+            (Action::Read, SecurableType::Volume) => vec![Privilege::ReadVolume],
+            (Action::Read, SecurableType::Function | SecurableType::RegisteredModel) => vec![Privilege::Execute],
+            (Action::Read, SecurableType::StorageCredential | SecurableType::ExternalLocation) => vec![Privilege::ReadFiles],
+            (Action::Write, SecurableType::Catalog | SecurableType::Schema | SecurableType::Table) => vec![Privilege::Modify],
+            (Action::Write, SecurableType::Volume) => vec![Privilege::WriteVolume],
+            (Action::Write, SecurableType::StorageCredential | SecurableType::ExternalLocation) => vec![Privilege::WriteFiles],
+            (Action::Execute, SecurableType::Catalog | SecurableType::Schema | SecurableType::Function | SecurableType::RegisteredModel) => vec![Privilege::Execute],
+            _ => vec![],
+        };
 
-    pub fn to_hash_map(&self) -> HashMap<String, String> {
+        if required.is_empty() {
+            return required;
+        }
+        let mut privileges: Vec<Privilege> = required;
+        privileges.push(Privilege::AllPrivileges);
+        privileges
+    }
+}
+impl std::fmt::Display for Action {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name: &str = match self {
+            Action::Read => "read",
+            Action::Write => "write",
+            Action::Execute => "execute",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+
+
+
+// short lived credentials vended by unity catalog, only the credential for the table's cloud is set
+#[derive(Debug, Deserialize, Clone)]
+pub struct TemporaryTableCredentials {
+    pub aws_temp_credentials: Option<AwsTemporaryCredentials>,
+    pub azure_user_delegation_sas: Option<AzureUserDelegationSas>,
+    pub gcp_oauth_token: Option<GcpOauthToken>,
+    pub expiration_time: i64, // epoch milliseconds
+    pub url: Option<String>,
+}
+impl TemporaryTableCredentials {
+    // true when the credentials expire within the buffer, so reads are not started with credentials about to lapse
+    pub fn expires_within(&self, buffer_ms: i64) -> bool {
+        let now: i64 = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_millis() as i64;
+        self.expiration_time - now <= buffer_ms
+    }
+
+    // storage options for open_table_with_storage_options
+    pub fn to_hash_map(&self, table_path: &str) -> Result<HashMap<String, String>, String> {
         let mut map = HashMap::new();
-        map.insert("azure_storage_account_name".to_string(), self.azure_storage_account_name.clone());
-        map.insert("azure_client_id".to_string(), self.azure_client_id.clone());
-        map.insert("azure_client_secret".to_string(), self.azure_client_secret.clone());
-        map.insert("azure_tenant_id".to_string(), self.azure_tenant_id.clone());
-        map
+        if let Some(sas) = &self.azure_user_delegation_sas {
+            // abfss://<container>@<account>.dfs.core.windows.net/<path>
+            let account: &str = table_path
+                .split('@')
+                .nth(1)
+                .and_then(|host| host.split('.').next())
+                .ok_or(format!("Unable to get the storage account from {}", table_path))?;
+            map.insert("azure_storage_account_name".to_string(), account.to_string());
+            map.insert("azure_storage_sas_token".to_string(), sas.sas_token.expose_secret().to_string());
+        } else if let Some(aws) = &self.aws_temp_credentials {
+            map.insert("aws_access_key_id".to_string(), aws.access_key_id.clone());
+            map.insert("aws_secret_access_key".to_string(), aws.secret_access_key.expose_secret().to_string());
+            map.insert("aws_session_token".to_string(), aws.session_token.expose_secret().to_string());
+        } else if self.gcp_oauth_token.is_some() {
+            return Err(String::from("GCP oauth tokens are not supported by the object store"));
+        } else {
+            return Err(String::from("Unity Catalog did not return any storage credentials"));
+        }
+        Ok(map)
     }
-}
\ No newline at end of file
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct AwsTemporaryCredentials {
+    pub access_key_id: String,
+    pub secret_access_key: Secret,
+    pub session_token: Secret,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct AzureUserDelegationSas {
+    pub sas_token: Secret,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct GcpOauthToken {
+    pub oauth_token: Secret,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assignment(principal: &str, privileges: Vec<Privilege>) -> PrivilegeAssignment {
+        PrivilegeAssignment {
+            object_name: String::from("my_catalog.my_schema.my_table"),
+            object_type: Some(SecurableType::Table),
+            principal: Some(principal.to_string()),
+            principal_type: None,
+            privileges: Some(privileges),
+        }
+    }
+
+    fn privileges_of(response: &PrivilegeAssignmentsResponse, principal: &str) -> Option<Vec<Privilege>> {
+        response.privilege_assignments.iter().flatten()
+            .find(|a| a.principal.as_deref() == Some(principal))
+            .and_then(|a| a.privileges.clone())
+    }
+
+    #[test]
+    fn apply_changes_adds_and_removes_privileges() {
+        let mut response: PrivilegeAssignmentsResponse = PrivilegeAssignmentsResponse {
+            privilege_assignments: Some(vec![assignment("analysts", vec![Privilege::Select])]),
+        };
+        response.apply_changes(&[
+            PermissionsChange::add("analysts", vec![Privilege::Select, Privilege::Modify]),
+            PermissionsChange::add("engineers", vec![Privilege::AllPrivileges]),
+        ]);
+        assert_eq!(privileges_of(&response, "analysts"), Some(vec![Privilege::Select, Privilege::Modify]));
+        assert_eq!(privileges_of(&response, "engineers"), Some(vec![Privilege::AllPrivileges]));
+
+        // new assignments take the securable of the existing ones
+        let engineers: &PrivilegeAssignment = response.privilege_assignments.iter().flatten()
+            .find(|a| a.principal.as_deref() == Some("engineers"))
+            .unwrap();
+        assert_eq!(engineers.object_name, "my_catalog.my_schema.my_table");
+        assert_eq!(engineers.object_type.as_ref().map(|t| t.to_string()), Some(String::from("table")));
+    }
+
+    #[test]
+    fn apply_changes_drops_principals_without_privileges() {
+        let mut response: PrivilegeAssignmentsResponse = PrivilegeAssignmentsResponse {
+            privilege_assignments: Some(vec![assignment("analysts", vec![Privilege::Select]), assignment("engineers", vec![Privilege::Select])]),
+        };
+        response.apply_changes(&[PermissionsChange::remove("analysts", vec![Privilege::Select])]);
+        assert_eq!(privileges_of(&response, "analysts"), None);
+        assert_eq!(privileges_of(&response, "engineers"), Some(vec![Privilege::Select]));
+    }
+
+    #[test]
+    fn privileges_round_trip_through_strings() {
+        let types: Vec<SecurableType> = vec![SecurableType::Catalog, SecurableType::StorageCredential, SecurableType::ExternalLocation, SecurableType::Metastore, SecurableType::Connection];
+        for privilege in types.iter().flat_map(|t| t.privileges()) {
+            let name: String = privilege.to_string();
+            assert_eq!(name.parse::<Privilege>(), Ok(privilege.clone()));
+            assert_eq!(Privilege::from(name), privilege);
+        }
+        assert_eq!(Privilege::from(String::from("MANAGE")), Privilege::Other(String::from("MANAGE")));
+        assert_eq!(Privilege::Other(String::from("MANAGE")).to_string(), "MANAGE");
+    }
+
+    #[test]
+    fn privileges_serialize_as_api_names() {
+        let privileges: Vec<Privilege> = serde_json::from_str(r#"["USE_CATALOG", "SELECT", "MANAGE"]"#).unwrap();
+        assert_eq!(privileges, vec![Privilege::UseCatalog, Privilege::Select, Privilege::Other(String::from("MANAGE"))]);
+        assert_eq!(serde_json::to_string(&privileges).unwrap(), r#"["USE_CATALOG","SELECT","MANAGE"]"#);
+    }
+
+    #[test]
+    fn rejects_privileges_not_grantable_on_the_securable() {
+        assert!(SecurableType::Table.validate_privileges(&[Privilege::Select, Privilege::Modify]).is_ok());
+        assert!(SecurableType::Table.validate_privileges(&[Privilege::UseCatalog]).is_err());
+    }
+
+    #[test]
+    fn scim_filters_are_quoted_and_encoded() {
+        let url: String = scim_filter_url("example.cloud.databricks.com", "Users", "userName", "a\"b@c.com");
+        assert_eq!(url, "https://example.cloud.databricks.com/api/2.0/preview/scim/v2/Users?filter=userName%20eq%20%22a%5C%22b%40c.com%22");
+        assert_eq!(percent_encode("data & analytics"), "data%20%26%20analytics");
+    }
+}
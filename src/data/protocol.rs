@@ -0,0 +1,297 @@
+// Delta protocol reader features for the readers that decode parquet files directly
+// https://github.com/delta-io/delta/blob/master/PROTOCOL.md
+use deltalake::kernel::{DeletionVectorDescriptor, MetadataValue, ReaderFeatures, StorageType, StructType};
+use deltalake::{DeltaTable, DeltaTableError, ObjectStore, Path};
+use deltalake::arrow::datatypes::{Field as ArrowField, Schema as ArrowSchema};
+use deltalake::arrow::record_batch::RecordBatch;
+use roaring::RoaringTreemap;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+const COLUMN_MAPPING_MODE: &str = "delta.columnMapping.mode";
+const PHYSICAL_NAME: &str = "delta.columnMapping.physicalName";
+// little endian magic number at the start of a serialized deletion vector
+const DELETION_VECTOR_MAGIC: u32 = 1681511377;
+const Z85_CHARS: &[u8] = b"0123456789abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ.-:+=^!/*?&<>()[]{}@%$#";
+
+
+/// Fails with the names of the reader features the table needs that the polars readers do not implement.
+///
+/// # Arguments
+///
+/// * `table` - The loaded delta table
+///
+/// # Examples
+///
+/// ```
+/// check_reader_features(&table)?;
+/// ```
+pub fn check_reader_features(table: &DeltaTable) -> Result<(), DeltaTableError> {
+    let protocol = table.protocol()?;
+    if protocol.min_reader_version > 3 {
+        return Err(DeltaTableError::Generic(format!(
+            "Table {} requires reader version {}, only versions up to 3 are supported.", table.table_uri(), protocol.min_reader_version
+        )));
+    }
+
+    let unsupported: Vec<String> = protocol.reader_features.iter()
+        .flatten()
+        .filter(|feature| !matches!(feature,
+            ReaderFeatures::ColumnMapping | ReaderFeatures::DeletionVectors | ReaderFeatures::TimestampWithoutTimezone
+        ))
+        .map(|feature| format!("{:?}", feature))
+        .collect();
+    if !unsupported.is_empty() {
+        return Err(DeltaTableError::Generic(format!(
+            "Table {} uses reader features that are not supported: {}. Use read_delta_table_as_datafusion.", table.table_uri(), unsupported.join(", ")
+        )));
+    }
+    Ok(())
+}
+
+
+// logical to physical names of top level columns, empty unless the table uses column mapping
+// nested struct fields keep their physical names
+#[derive(Debug, Clone, Default)]
+pub struct ColumnMapping {
+    logical_to_physical: HashMap<String, String>,
+}
+impl ColumnMapping {
+    pub fn from_table(table: &DeltaTable) -> Result<ColumnMapping, DeltaTableError> {
+        let mode: Option<String> = table.metadata()?.configuration.get(COLUMN_MAPPING_MODE).cloned().flatten();
+        match mode.as_deref() {
+            None | Some("none") => Ok(ColumnMapping::default()),
+            Some("name") | Some("id") => Ok(ColumnMapping::from_schema(table.get_schema()?)),
+            Some(other) => Err(DeltaTableError::Generic(format!("Column mapping mode {} is not supported.", other))),
+        }
+    }
+
+    fn from_schema(schema: &StructType) -> ColumnMapping {
+        let mut logical_to_physical: HashMap<String, String> = HashMap::new();
+        for field in schema.fields() {
+            if let Some(MetadataValue::String(physical)) = field.metadata().get(PHYSICAL_NAME) {
+                logical_to_physical.insert(field.name().clone(), physical.clone());
+            }
+        }
+        ColumnMapping { logical_to_physical }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.logical_to_physical.is_empty()
+    }
+
+    pub fn physical(&self, logical: &str) -> String {
+        self.logical_to_physical.get(logical).cloned().unwrap_or(logical.to_string())
+    }
+
+    pub fn logical(&self, physical: &str) -> String {
+        self.logical_to_physical.iter()
+            .find(|(_, p)| p.as_str() == physical)
+            .map(|(l, _)| l.clone())
+            .unwrap_or(physical.to_string())
+    }
+
+    // partition values and file stats in the delta log are keyed by physical name
+    pub fn logical_keys<V>(&self, values: HashMap<String, V>) -> HashMap<String, V> {
+        if self.is_empty() {
+            return values;
+        }
+        values.into_iter().map(|(k, v)| (self.logical(&k), v)).collect()
+    }
+
+    pub fn rename_polars(&self, df: &mut polars::prelude::DataFrame) -> Result<(), DeltaTableError> {
+        if self.is_empty() {
+            return Ok(());
+        }
+        let physical_names: Vec<String> = df.get_column_names().iter().map(|n| n.to_string()).collect();
+        for physical in physical_names {
+            let logical: String = self.logical(&physical);
+            if logical != physical {
+                df.rename(&physical, &logical)
+                    .map_err(|e| DeltaTableError::Generic(format!("Failed to rename column {}: {}", physical, e)))?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn rename_batch(&self, batch: RecordBatch) -> Result<RecordBatch, DeltaTableError> {
+        if self.is_empty() {
+            return Ok(batch);
+        }
+        let fields: Vec<ArrowField> = batch.schema().fields().iter()
+            .map(|f| f.as_ref().clone().with_name(self.logical(f.name())))
+            .collect();
+        Ok(RecordBatch::try_new(Arc::new(ArrowSchema::new(fields)), batch.columns().to_vec())?)
+    }
+}
+
+
+/// Loads the row indexes deleted from a data file by its deletion vector.
+///
+/// # Arguments
+///
+/// * `object_store` - The object store rooted at the table location
+/// * `table_uri` - The table location, absolute deletion vector paths must be under it
+/// * `descriptor` - The deletion vector of the file's add action
+///
+/// # Examples
+///
+/// ```
+/// let deleted: RoaringTreemap = load_deleted_rows(&object_store, &table.table_uri(), &descriptor).await?;
+/// ```
+pub async fn load_deleted_rows(object_store: &Arc<dyn ObjectStore>, table_uri: &str, descriptor: &DeletionVectorDescriptor) -> Result<RoaringTreemap, DeltaTableError> {
+    let size: usize = descriptor.size_in_bytes as usize;
+    let data: Vec<u8> = match descriptor.storage_type {
+        StorageType::Inline => {
+            let mut decoded: Vec<u8> = z85_decode(&descriptor.path_or_inline_dv)?;
+            decoded.truncate(size);
+            decoded
+        }
+        StorageType::UuidRelativePath | StorageType::AbsolutePath => {
+            let path: Path = deletion_vector_path(table_uri, descriptor)?;
+            // the file holds a version byte, then for each vector its size, the data and a checksum
+            let start: usize = descriptor.offset.unwrap_or(1) as usize + 4;
+            object_store.get_range(&path, start..start + size).await?.to_vec()
+        }
+    };
+
+    let magic: Option<u32> = data.get(..4).map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]));
+    if magic != Some(DELETION_VECTOR_MAGIC) {
+        return Err(DeltaTableError::Generic(String::from("Deletion vector has an invalid magic number.")));
+    }
+    RoaringTreemap::deserialize_from(&data[4..])
+        .map_err(|e| DeltaTableError::Generic(format!("Failed to read deletion vector: {}", e)))
+}
+
+// the deletion vector file relative to the table root
+fn deletion_vector_path(table_uri: &str, descriptor: &DeletionVectorDescriptor) -> Result<Path, DeltaTableError> {
+    let value: &str = &descriptor.path_or_inline_dv;
+    match descriptor.storage_type {
+        StorageType::UuidRelativePath => {
+            // an optional random directory prefix followed by the z85 encoded uuid
+            if value.len() < 20 {
+                return Err(DeltaTableError::Generic(format!("Invalid deletion vector path {}", value)));
+            }
+            let (prefix, encoded) = value.split_at(value.len() - 20);
+            let uuid: Vec<u8> = z85_decode(encoded)?;
+            let hex: String = uuid.iter().map(|b| format!("{:02x}", b)).collect();
+            let file_name: String = format!(
+                "deletion_vector_{}-{}-{}-{}-{}.bin", &hex[0..8], &hex[8..12], &hex[12..16], &hex[16..20], &hex[20..32]
+            );
+            if prefix.is_empty() {
+                Ok(Path::from(file_name))
+            } else {
+                Ok(Path::from(format!("{}/{}", prefix, file_name)))
+            }
+        }
+        _ => {
+            let root: &str = table_uri.trim_end_matches('/');
+            let relative: &str = value.strip_prefix(root).map(|p| p.trim_start_matches('/'))
+                .ok_or_else(|| DeltaTableError::Generic(format!("Deletion vector {} is outside of the table location.", value)))?;
+            Path::from_url_path(relative)
+                .map_err(|e| DeltaTableError::Generic(format!("Invalid deletion vector path {}: {}", value, e)))
+        }
+    }
+}
+
+// z85 decodes every 5 characters into 4 big endian bytes
+fn z85_decode(encoded: &str) -> Result<Vec<u8>, DeltaTableError> {
+    let invalid = || DeltaTableError::Generic(format!("Invalid z85 data {}", encoded));
+    if !encoded.len().is_multiple_of(5) {
+        return Err(invalid());
+    }
+    let mut decoded: Vec<u8> = Vec::with_capacity(encoded.len() / 5 * 4);
+    for chunk in encoded.as_bytes().chunks(5) {
+        let mut value: u64 = 0;
+        for c in chunk {
+            let digit: usize = Z85_CHARS.iter().position(|z| z == c).ok_or_else(invalid)?;
+            value = value * 85 + digit as u64;
+        }
+        let value: u32 = u32::try_from(value).map_err(|_| invalid())?;
+        decoded.extend_from_slice(&value.to_be_bytes());
+    }
+    Ok(decoded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    // a table with only a first commit, holding the given protocol and the id column with the given field metadata
+    async fn table_with_protocol(dir: &TempDir, protocol: &str, configuration: &str, id_metadata: &str) -> DeltaTable {
+        let schema: String = format!(r#"{{"type":"struct","fields":[{{"name":"id","type":"long","nullable":true,"metadata":{}}}]}}"#, id_metadata);
+        let metadata: serde_json::Value = serde_json::json!({"metaData": {
+            "id": "5fba94ed-9794-4965-ba6e-6ee3c0d22af9",
+            "format": {"provider": "parquet", "options": {}},
+            "schemaString": schema,
+            "partitionColumns": [],
+            "configuration": serde_json::from_str::<serde_json::Value>(configuration).unwrap(),
+            "createdTime": 0,
+        }});
+        let log_dir = dir.path().join("_delta_log");
+        std::fs::create_dir_all(&log_dir).unwrap();
+        std::fs::write(log_dir.join("00000000000000000000.json"), format!("{}\n{}\n", protocol, metadata)).unwrap();
+        deltalake::open_table(dir.path().to_str().unwrap()).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn supported_reader_features_pass() {
+        let dir: TempDir = TempDir::new().unwrap();
+        let table: DeltaTable = table_with_protocol(&dir, r#"{"protocol":{"minReaderVersion":1,"minWriterVersion":2}}"#, "{}", "{}").await;
+        assert!(check_reader_features(&table).is_ok());
+
+        let dir: TempDir = TempDir::new().unwrap();
+        let protocol: &str = r#"{"protocol":{"minReaderVersion":3,"minWriterVersion":7,"readerFeatures":["deletionVectors","columnMapping"],"writerFeatures":["deletionVectors","columnMapping"]}}"#;
+        let table: DeltaTable = table_with_protocol(&dir, protocol, "{}", "{}").await;
+        assert!(check_reader_features(&table).is_ok());
+    }
+
+    #[tokio::test]
+    async fn unsupported_reader_features_fail() {
+        let dir: TempDir = TempDir::new().unwrap();
+        let protocol: &str = r#"{"protocol":{"minReaderVersion":3,"minWriterVersion":7,"readerFeatures":["v2Checkpoint"],"writerFeatures":["v2Checkpoint"]}}"#;
+        let table: DeltaTable = table_with_protocol(&dir, protocol, "{}", "{}").await;
+        let message: String = check_reader_features(&table).unwrap_err().to_string();
+        assert!(message.contains("V2Checkpoint"));
+    }
+
+    #[tokio::test]
+    async fn column_mapping_translates_physical_names() {
+        let dir: TempDir = TempDir::new().unwrap();
+        let protocol: &str = r#"{"protocol":{"minReaderVersion":2,"minWriterVersion":5}}"#;
+        let metadata: &str = r#"{"delta.columnMapping.id":1,"delta.columnMapping.physicalName":"col-5f422f40"}"#;
+        let table: DeltaTable = table_with_protocol(&dir, protocol, r#"{"delta.columnMapping.mode":"name"}"#, metadata).await;
+        let mapping: ColumnMapping = ColumnMapping::from_table(&table).unwrap();
+        assert_eq!(mapping.physical("id"), "col-5f422f40");
+        assert_eq!(mapping.logical("col-5f422f40"), "id");
+        assert_eq!(mapping.logical("other"), "other");
+        let keyed: HashMap<String, i64> = mapping.logical_keys(HashMap::from([(String::from("col-5f422f40"), 1)]));
+        assert_eq!(keyed.get("id"), Some(&1));
+    }
+
+    #[test]
+    fn decodes_z85() {
+        // the example from https://rfc.zeromq.org/spec/32/
+        assert_eq!(z85_decode("HelloWorld").unwrap(), vec![0x86, 0x4F, 0xD2, 0x6F, 0xB5, 0x59, 0xF7, 0x5B]);
+        assert!(z85_decode("Hello").is_ok());
+        assert!(z85_decode("Hell").is_err());
+        assert!(z85_decode("Hell~").is_err());
+    }
+
+    #[test]
+    fn deletion_vector_paths_are_relative_to_the_table() {
+        let descriptor = |storage_type: StorageType, path: &str| DeletionVectorDescriptor {
+            storage_type,
+            path_or_inline_dv: path.to_string(),
+            offset: Some(1),
+            size_in_bytes: 36,
+            cardinality: 2,
+        };
+        let relative: Path = deletion_vector_path("file:///tmp/table", &descriptor(StorageType::UuidRelativePath, "abHelloWorldHelloWorld")).unwrap();
+        assert_eq!(relative.as_ref(), "ab/deletion_vector_864fd26f-b559-f75b-864f-d26fb559f75b.bin");
+        let absolute: Path = deletion_vector_path("file:///tmp/table/", &descriptor(StorageType::AbsolutePath, "file:///tmp/table/dv.bin")).unwrap();
+        assert_eq!(absolute.as_ref(), "dv.bin");
+        assert!(deletion_vector_path("file:///tmp/table", &descriptor(StorageType::AbsolutePath, "file:///tmp/other/dv.bin")).is_err());
+    }
+}
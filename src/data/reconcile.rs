@@ -0,0 +1,250 @@
+use super::permissions::{BoxError, Permissions, PermissionsChange, Privilege, PrivilegeAssignmentsResponse, SecurableType};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::Path;
+
+
+// desired state of grants, loaded from a yaml or json file
+//
+// securables:
+//   - securable_type: schema
+//     full_name: rac_demo_catalog.retail_pos
+//     grants:
+//       - principal: analysts
+//         privileges: [USE_SCHEMA, SELECT]
+#[derive(Debug, Deserialize, Clone)]
+pub struct DesiredState {
+    pub securables: Vec<DesiredSecurable>,
+}
+impl DesiredState {
+    /// Loads a desired state file. Files ending in `.yaml` or `.yml` are parsed as yaml, anything else as json.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path to the desired state file
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let desired: DesiredState = DesiredState::from_file("grants.yaml")?;
+    /// ```
+    pub fn from_file(path: &str) -> Result<Self, BoxError> {
+        let contents: String = std::fs::read_to_string(path)?;
+        let extension: &str = Path::new(path).extension().and_then(|e| e.to_str()).unwrap_or_default();
+        let desired: DesiredState = match extension {
+            "yaml" | "yml" => serde_yaml::from_str(&contents)?,
+            _ => serde_json::from_str(&contents)?,
+        };
+        Ok(desired)
+    }
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct DesiredSecurable {
+    pub securable_type: SecurableType,
+    pub full_name: String,
+    pub grants: Vec<DesiredGrant>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct DesiredGrant {
+    pub principal: String,
+    pub privileges: Vec<Privilege>,
+}
+
+
+// the grants and revokes required to move live grants to the desired state
+#[derive(Debug, Clone)]
+pub struct ReconcilePlan {
+    pub changes: Vec<PlannedChange>,
+}
+impl ReconcilePlan {
+    // true when live grants differ from the desired state, used to fail CI on drift
+    pub fn has_drift(&self) -> bool {
+        !self.changes.is_empty()
+    }
+
+    pub fn print(&self) {
+        if !self.has_drift() {
+            log::info!("No changes. Live grants match the desired state.");
+            return;
+        }
+        for change in &self.changes {
+            for p in &change.grant {
+                log::info!("+ GRANT {} ON {} {} TO {}", p, change.securable_type.to_string(), change.full_name, change.principal);
+            }
+            for p in &change.revoke {
+                log::info!("- REVOKE {} ON {} {} FROM {}", p, change.securable_type.to_string(), change.full_name, change.principal);
+            }
+        }
+        let grants: usize = self.changes.iter().map(|c| c.grant.len()).sum();
+        let revokes: usize = self.changes.iter().map(|c| c.revoke.len()).sum();
+        log::info!("Plan: {} to grant, {} to revoke.", grants, revokes);
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct PlannedChange {
+    pub securable_type: SecurableType,
+    pub full_name: String,
+    pub principal: String,
+    pub grant: Vec<Privilege>,
+    pub revoke: Vec<Privilege>,
+}
+
+
+#[derive(Clone)]
+pub struct Reconciler {
+    permissions_client: Permissions,
+}
+impl Reconciler {
+    pub fn new(permissions_client: Permissions) -> Self {
+        Reconciler { permissions_client }
+    }
+
+    /// Diffs the desired state against live grants and returns the changes required.
+    /// Only principals listed for a securable are managed, grants to anyone else are left untouched.
+    ///
+    /// # Arguments
+    ///
+    /// * `desired` - The desired state of grants
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let plan: ReconcilePlan = reconciler.plan(&desired).await?;
+    /// plan.print();
+    /// ```
+    pub async fn plan(&self, desired: &DesiredState) -> Result<ReconcilePlan, BoxError> {
+        let mut changes: Vec<PlannedChange> = Vec::new();
+
+        for securable in &desired.securables {
+            for grant in &securable.grants {
+                securable.securable_type.validate_privileges(&grant.privileges)?;
+
+                let live: Vec<Privilege> = self.live_privileges(securable, &grant.principal).await?;
+                if let Some(change) = plan_grant(securable, grant, &live) {
+                    changes.push(change);
+                }
+            }
+        }
+
+        Ok(ReconcilePlan { changes })
+    }
+
+    /// Applies a plan, one permissions update per securable.
+    ///
+    /// # Arguments
+    ///
+    /// * `plan` - The plan returned by `plan`
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// reconciler.apply(&plan).await?;
+    /// ```
+    pub async fn apply(&self, plan: &ReconcilePlan) -> Result<(), BoxError> {
+        let mut grouped: HashMap<(String, String), (SecurableType, Vec<PermissionsChange>)> = HashMap::new();
+        for change in &plan.changes {
+            let entry = grouped
+                .entry((change.securable_type.to_string(), change.full_name.clone()))
+                .or_insert_with(|| (change.securable_type.clone(), Vec::new()));
+            entry.1.push(PermissionsChange {
+                principal: change.principal.clone(),
+                add: change.grant.clone(),
+                remove: change.revoke.clone(),
+            });
+        }
+
+        for ((_, full_name), (securable_type, changes)) in grouped {
+            log::info!("Applying {} change(s) to {} {}", changes.len(), securable_type.to_string(), full_name);
+            self.permissions_client.update_permissions(securable_type, &full_name, changes, false).await?;
+        }
+        Ok(())
+    }
+
+    // privileges granted directly to the principal on this securable, inherited grants are ignored
+    async fn live_privileges(&self, securable: &DesiredSecurable, principal: &str) -> Result<Vec<Privilege>, BoxError> {
+        let assignments: PrivilegeAssignmentsResponse = self.permissions_client.fetch_permissions(securable.securable_type.clone(), &securable.full_name, principal).await?;
+
+        let mut privileges: Vec<Privilege> = Vec::new();
+        for a in assignments.privilege_assignments.unwrap_or_default() {
+            if a.object_name == securable.full_name && a.principal.as_deref() == Some(principal) {
+                privileges.extend(a.privileges.unwrap_or_default());
+            }
+        }
+        Ok(privileges)
+    }
+}
+
+// the privileges to grant and revoke so the principal holds exactly the desired privileges, None when nothing changes
+fn plan_grant(securable: &DesiredSecurable, grant: &DesiredGrant, live: &[Privilege]) -> Option<PlannedChange> {
+    let to_grant: Vec<Privilege> = grant.privileges.iter().filter(|p| !live.contains(p)).cloned().collect();
+    let to_revoke: Vec<Privilege> = live.iter().filter(|p| !grant.privileges.contains(p)).cloned().collect();
+    if to_grant.is_empty() && to_revoke.is_empty() {
+        return None;
+    }
+    Some(PlannedChange {
+        securable_type: securable.securable_type.clone(),
+        full_name: securable.full_name.clone(),
+        principal: grant.principal.clone(),
+        grant: to_grant,
+        revoke: to_revoke,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use tempfile::NamedTempFile;
+
+    fn securable(grants: Vec<DesiredGrant>) -> DesiredSecurable {
+        DesiredSecurable {
+            securable_type: SecurableType::Schema,
+            full_name: String::from("rac_demo_catalog.retail_pos"),
+            grants,
+        }
+    }
+
+    fn grant(privileges: Vec<Privilege>) -> DesiredGrant {
+        DesiredGrant { principal: String::from("analysts"), privileges }
+    }
+
+    #[test]
+    fn no_change_when_live_grants_match() {
+        let desired: DesiredGrant = grant(vec![Privilege::UseSchema, Privilege::Select]);
+        let live: Vec<Privilege> = vec![Privilege::Select, Privilege::UseSchema];
+        assert!(plan_grant(&securable(vec![desired.clone()]), &desired, &live).is_none());
+    }
+
+    #[test]
+    fn grants_missing_and_revokes_extra_privileges() {
+        let desired: DesiredGrant = grant(vec![Privilege::UseSchema, Privilege::Select]);
+        let live: Vec<Privilege> = vec![Privilege::UseSchema, Privilege::Modify];
+        let change: PlannedChange = plan_grant(&securable(vec![desired.clone()]), &desired, &live).unwrap();
+        assert_eq!(change.principal, "analysts");
+        assert_eq!(change.full_name, "rac_demo_catalog.retail_pos");
+        assert_eq!(change.grant, vec![Privilege::Select]);
+        assert_eq!(change.revoke, vec![Privilege::Modify]);
+    }
+
+    #[test]
+    fn empty_desired_privileges_revoke_everything() {
+        let desired: DesiredGrant = grant(Vec::new());
+        let live: Vec<Privilege> = vec![Privilege::Select];
+        let change: PlannedChange = plan_grant(&securable(vec![desired.clone()]), &desired, &live).unwrap();
+        assert!(change.grant.is_empty());
+        assert_eq!(change.revoke, vec![Privilege::Select]);
+    }
+
+    #[test]
+    fn loads_yaml_desired_state() {
+        let mut file: NamedTempFile = tempfile::Builder::new().suffix(".yaml").tempfile().unwrap();
+        writeln!(file, "securables:\n  - securable_type: schema\n    full_name: rac_demo_catalog.retail_pos\n    grants:\n      - principal: analysts\n        privileges: [USE_SCHEMA, SELECT]").unwrap();
+        let desired: DesiredState = DesiredState::from_file(file.path().to_str().unwrap()).unwrap();
+        assert_eq!(desired.securables.len(), 1);
+        assert_eq!(desired.securables[0].securable_type.to_string(), "schema");
+        assert_eq!(desired.securables[0].grants[0].privileges, vec![Privilege::UseSchema, Privilege::Select]);
+    }
+}
@@ -0,0 +1,238 @@
+use super::secret::Secret;
+use serde::Deserialize;
+use std::collections::HashMap;
+
+
+// cloud a table lives in, taken from the scheme of its storage location
+#[derive(Debug, Clone, PartialEq)]
+pub enum StorageScheme {
+    Azure,
+    S3,
+    Gcs,
+    Local,
+}
+impl StorageScheme {
+    pub fn from_location(location: &str) -> Result<Self, String> {
+        match location.split_once("://").map(|(scheme, _)| scheme) {
+            Some("abfss") | Some("abfs") | Some("az") | Some("adl") | Some("azure") => Ok(StorageScheme::Azure),
+            Some("s3") | Some("s3a") => Ok(StorageScheme::S3),
+            Some("gs") => Ok(StorageScheme::Gcs),
+            Some("file") | None => Ok(StorageScheme::Local),
+            Some(other) => Err(format!("Unsupported storage scheme '{}' in {}", other, location)),
+        }
+    }
+}
+
+
+// how to authenticate against an azure storage account
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum AzureCredential {
+    ClientSecret { client_id: String, client_secret: Secret, tenant_id: String },
+    SasToken { sas_token: Secret },
+    AccountKey { account_key: Secret },
+    ManagedIdentity { client_id: Option<String> }, // client id of a user assigned identity
+}
+
+// https://docs.rs/object_store/latest/object_store/azure/enum.AzureConfigKey.html#variants
+// secrets are only exposed in the map handed to the object store
+#[derive(Debug, Clone, Deserialize)]
+pub struct AzureStorageOptions {
+    pub account_name: String,
+    pub credential: AzureCredential,
+}
+impl AzureStorageOptions {
+    pub fn to_hash_map(&self) -> HashMap<String, String> {
+        let mut map = HashMap::new();
+        map.insert("azure_storage_account_name".to_string(), self.account_name.clone());
+        match &self.credential {
+            AzureCredential::ClientSecret { client_id, client_secret, tenant_id } => {
+                map.insert("azure_client_id".to_string(), client_id.clone());
+                map.insert("azure_client_secret".to_string(), client_secret.expose_secret().to_string());
+                map.insert("azure_tenant_id".to_string(), tenant_id.clone());
+            }
+            AzureCredential::SasToken { sas_token } => {
+                map.insert("azure_storage_sas_token".to_string(), sas_token.expose_secret().to_string());
+            }
+            AzureCredential::AccountKey { account_key } => {
+                map.insert("azure_storage_account_key".to_string(), account_key.expose_secret().to_string());
+            }
+            AzureCredential::ManagedIdentity { client_id } => {
+                // the object store falls back to the instance metadata endpoint when no secret is given
+                if let Some(id) = client_id {
+                    map.insert("azure_client_id".to_string(), id.clone());
+                }
+            }
+        }
+        map
+    }
+}
+
+// https://docs.rs/object_store/latest/object_store/aws/enum.AmazonS3ConfigKey.html#variants
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct S3StorageOptions {
+    pub access_key_id: Option<String>,
+    pub secret_access_key: Option<Secret>,
+    pub session_token: Option<Secret>,
+    pub region: Option<String>,
+    pub endpoint: Option<String>, // custom endpoint i.e. minio
+    pub allow_http: Option<bool>,
+    pub allow_unsafe_rename: Option<bool>, // required to write without a locking provider
+}
+impl S3StorageOptions {
+    pub fn to_hash_map(&self) -> HashMap<String, String> {
+        let mut map = HashMap::new();
+        if let Some(v) = &self.access_key_id {
+            map.insert("aws_access_key_id".to_string(), v.clone());
+        }
+        if let Some(v) = &self.secret_access_key {
+            map.insert("aws_secret_access_key".to_string(), v.expose_secret().to_string());
+        }
+        if let Some(v) = &self.session_token {
+            map.insert("aws_session_token".to_string(), v.expose_secret().to_string());
+        }
+        map.extend(self.location_options());
+        map
+    }
+
+    // options that describe where the bucket is rather than who is reading it
+    pub fn location_options(&self) -> HashMap<String, String> {
+        let mut map = HashMap::new();
+        if let Some(v) = &self.region {
+            map.insert("aws_region".to_string(), v.clone());
+        }
+        if let Some(v) = &self.endpoint {
+            map.insert("aws_endpoint_url".to_string(), v.clone());
+        }
+        if let Some(v) = self.allow_http {
+            map.insert("aws_allow_http".to_string(), v.to_string());
+        }
+        if let Some(v) = self.allow_unsafe_rename {
+            map.insert("aws_s3_allow_unsafe_rename".to_string(), v.to_string());
+        }
+        map
+    }
+}
+
+// https://docs.rs/object_store/latest/object_store/gcp/enum.GoogleConfigKey.html#variants
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct GcsStorageOptions {
+    pub service_account_path: Option<String>,
+    pub service_account_key: Option<Secret>, // the service account json
+}
+impl GcsStorageOptions {
+    pub fn to_hash_map(&self) -> HashMap<String, String> {
+        let mut map = HashMap::new();
+        if let Some(v) = &self.service_account_path {
+            map.insert("google_service_account".to_string(), v.clone());
+        }
+        if let Some(v) = &self.service_account_key {
+            map.insert("google_service_account_key".to_string(), v.expose_secret().to_string());
+        }
+        map
+    }
+}
+
+
+// storage options for every cloud the reader may need, the options used are chosen by a table's storage location
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct StorageOptions {
+    pub azure: Option<AzureStorageOptions>,
+    pub s3: Option<S3StorageOptions>,
+    pub gcs: Option<GcsStorageOptions>,
+}
+impl StorageOptions {
+    pub fn new() -> Self {
+        StorageOptions::default()
+    }
+
+    pub fn with_azure(mut self, azure: AzureStorageOptions) -> Self {
+        self.azure = Some(azure);
+        self
+    }
+
+    pub fn with_s3(mut self, s3: S3StorageOptions) -> Self {
+        self.s3 = Some(s3);
+        self
+    }
+
+    pub fn with_gcs(mut self, gcs: GcsStorageOptions) -> Self {
+        self.gcs = Some(gcs);
+        self
+    }
+
+    // registers the object store handlers for every supported cloud
+    pub fn register_handlers() {
+        deltalake::azure::register_handlers(None);
+        deltalake::aws::register_handlers(None);
+        deltalake::gcp::register_handlers(None);
+    }
+
+    /// Returns the options for open_table_with_storage_options based on the scheme of the location.
+    ///
+    /// # Arguments
+    ///
+    /// * `location` - The storage location of the table i.e. `abfss://container@account.dfs.core.windows.net/table`
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let options: HashMap<String, String> = storage_options.for_location(&table_path)?;
+    /// ```
+    pub fn for_location(&self, location: &str) -> Result<HashMap<String, String>, String> {
+        let missing = |cloud: &str| format!("No {} storage options are configured for {}", cloud, location);
+        match StorageScheme::from_location(location)? {
+            StorageScheme::Azure => self.azure.as_ref().map(|o| o.to_hash_map()).ok_or_else(|| missing("azure")),
+            StorageScheme::S3 => self.s3.as_ref().map(|o| o.to_hash_map()).ok_or_else(|| missing("s3")),
+            StorageScheme::Gcs => self.gcs.as_ref().map(|o| o.to_hash_map()).ok_or_else(|| missing("gcs")),
+            StorageScheme::Local => Ok(HashMap::new()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scheme_comes_from_the_location() {
+        assert_eq!(StorageScheme::from_location("abfss://container@account.dfs.core.windows.net/table"), Ok(StorageScheme::Azure));
+        assert_eq!(StorageScheme::from_location("az://container/table"), Ok(StorageScheme::Azure));
+        assert_eq!(StorageScheme::from_location("s3://bucket/table"), Ok(StorageScheme::S3));
+        assert_eq!(StorageScheme::from_location("s3a://bucket/table"), Ok(StorageScheme::S3));
+        assert_eq!(StorageScheme::from_location("gs://bucket/table"), Ok(StorageScheme::Gcs));
+        assert_eq!(StorageScheme::from_location("file:///tmp/table"), Ok(StorageScheme::Local));
+        assert_eq!(StorageScheme::from_location("/tmp/table"), Ok(StorageScheme::Local));
+        assert!(StorageScheme::from_location("hdfs://namenode/table").is_err());
+    }
+
+    #[test]
+    fn options_are_chosen_by_location() {
+        let options: StorageOptions = StorageOptions::new().with_s3(S3StorageOptions {
+            access_key_id: Some(String::from("key")),
+            secret_access_key: Some(Secret::from("secret")),
+            region: Some(String::from("us-east-1")),
+            ..Default::default()
+        });
+        let s3: HashMap<String, String> = options.for_location("s3://bucket/table").unwrap();
+        assert_eq!(s3.get("aws_access_key_id").map(String::as_str), Some("key"));
+        assert_eq!(s3.get("aws_secret_access_key").map(String::as_str), Some("secret"));
+        assert_eq!(s3.get("aws_region").map(String::as_str), Some("us-east-1"));
+        assert!(options.for_location("/tmp/table").unwrap().is_empty());
+        assert!(options.for_location("abfss://container@account.dfs.core.windows.net/table").unwrap_err().contains("azure"));
+    }
+
+    #[test]
+    fn location_options_leave_out_credentials() {
+        let s3: S3StorageOptions = S3StorageOptions {
+            access_key_id: Some(String::from("key")),
+            endpoint: Some(String::from("http://localhost:9000")),
+            allow_http: Some(true),
+            ..Default::default()
+        };
+        let location: HashMap<String, String> = s3.location_options();
+        assert!(!location.contains_key("aws_access_key_id"));
+        assert_eq!(location.get("aws_endpoint_url").map(String::as_str), Some("http://localhost:9000"));
+        assert_eq!(location.get("aws_allow_http").map(String::as_str), Some("true"));
+    }
+}
@@ -1,7 +1,7 @@
 use dotenv::dotenv;
 use log;
-use std::env;
-use std::io::Error;
+use std::io::{Error, ErrorKind};
+pub mod config;
 pub mod sql {
     pub mod sql_client;
 }
@@ -10,10 +10,16 @@ pub mod data{
     pub mod permissions;
     pub mod api_client;
     pub mod delta;
+    pub mod reconcile;
+    pub mod audit;
+    pub mod storage;
+    pub mod secret;
+    pub mod filter;
+    pub mod protocol;
 }
 
-use data::delta::DeltaLakeReader;
-use data::permissions::AzureDataLakeGen2Options;
+use data::delta::{DeltaLakeReader, ReadOptions};
+use config::Config;
 
 
 #[tokio::main]
@@ -24,10 +30,13 @@ async fn main() -> Result<(), Error> {
     .filter_level(log::LevelFilter::Info)
     .init();
 
-    let db_token: String = env::var("DB_TOKEN").expect("DB_TOKEN not set");
-    let workspace_name: String = env::var("WORKSPACE_NAME").expect("WORKSPACE_NAME not set");
-    let database_url: String = env::var("DATABASE_URL").expect("DATABASE_URL not set");
-    let migrations_path: String = env::var("MIGRATIONS_PATH").expect("MIGRATIONS_PATH not set");
+    // config.toml profile, then environment variables (including .env), then command line arguments
+    let config: Config = Config::load().map_err(|e| Error::new(ErrorKind::InvalidInput, e.to_string()))?;
+    log::info!("Using profile {} for workspace {}", config.profile, config.workspace_name);
+    let db_token: data::secret::Secret = config.db_token.clone();
+    let workspace_name: String = config.workspace_name.clone();
+    let database_url: String = config.database_url.clone();
+    let migrations_path: String = config.migrations_path.clone();
 
     // Setup SQL
     let sql_client: sql::sql_client::SqlClient = sql::sql_client::SqlClient::new(&database_url).await.unwrap();
@@ -39,21 +48,30 @@ async fn main() -> Result<(), Error> {
     // let _catalog_update: Result<(), Error> = metastore_client.refresh_catalogs().await;
     // let _schema_update: Result<(), Error> = metastore_client.refresh_all_schemas().await;
     // let _table_update: Result<(), Error> = metastore_client.refresh_all_tables().await;
+    // let _credential_update = metastore_client.refresh_storage_credentials().await;
+    // let _location_update = metastore_client.refresh_external_locations().await;
+    // let table_credentials = metastore_client.resolve_table_credentials().await.unwrap();
+    // Several workspaces can be mirrored into the same database, lists take None to span all of them
+    // let prod_client = data::metastore::MetastoreClient::new(String::from("adb-123.4.azuredatabricks.net"), prod_token, database_url.clone()).await;
+    // let failed: Vec<String> = data::metastore::refresh_workspaces(&[metastore_client.clone(), prod_client]).await;
 
     // Testing various gets/list/refresh commands
-    // let cats = sql_client.list_catalogs(Some("rac")).await;
-    // let schs = sql_client.list_schemas(Some("rac_demo_catalog"), Some("product")).await;
-    // let ts = sql_client.list_tables(Some("rac_demo_catalog"), Some("productcopy_demo"), Some("clean")).await;
+    // let cats = sql_client.list_catalogs(Some(&workspace_name), Some("rac")).await;
+    // let schs = sql_client.list_schemas(None, Some("rac_demo_catalog"), Some("product")).await;
+    // let ts = sql_client.list_tables(None, Some("rac_demo_catalog"), Some("productcopy_demo"), Some("clean")).await;
 
     ////////// Permissions 
     // let active_user: api::permissions::User = permissions_client.authenticate_user("ryan.chynoweth@databricks.com", &api_client.db_token).await?;
-    let principal: &str = "ryan.chynoweth@databricks.com";
+    let principal: &str = &config.principal;
     let schema_type: data::permissions::SecurableType = data::permissions::SecurableType::Schema;
     let catalog_type: data::permissions::SecurableType = data::permissions::SecurableType::Catalog;
     // let perms: api::permissions::PrivilegeAssignmentsResponse = permissions_client.fetch_permissions(schema_type.clone(), "rac_demo_catalog.retail_pos", principal).await?;
     // let owner = permissions_client.get_object_owner(schema_type.clone(), "rac_demo_catalog.retail_pos").await?;
     // log::info!("{} - {}", owner.full_name, owner.owner);
 
+    // let readable: bool = permissions_client.can(Action::Read, SecurableType::Table, "rac_demo_catalog.productcopy_demo.clean", principal).await?;
+    // let volume_readable: bool = permissions_client.can(Action::Read, SecurableType::Volume, "rac_demo_catalog.rust_schema.landing", principal).await?;
+    // let model_executable: bool = permissions_client.can(Action::Execute, SecurableType::RegisteredModel, "rac_demo_catalog.rust_schema.forecaster", principal).await?;
     // let readable: bool = permissions_client.can_read(schema_type.clone(), "rac_demo_catalog.productcopy_demo", principal).await?;
     // println!("{}", readable);
 
@@ -63,18 +81,71 @@ async fn main() -> Result<(), Error> {
     // let writes: bool = permissions_client.can_write(schema_type.clone(), "main.abs_dev", principal).await?;
     // println!("{}", writes);
 
+    // let grants = permissions_client.grant(schema_type.clone(), "rac_demo_catalog.retail_pos", principal, vec![Privilege::UseSchema, Privilege::Select], true).await.unwrap();
+    // let revokes = permissions_client.revoke(schema_type.clone(), "rac_demo_catalog.retail_pos", principal, vec![Privilege::Modify], true).await.unwrap();
+
+    // Mirror grants locally for access reviews - run after the metastore refresh
+    // let _permissions_update = permissions_client.refresh_all_permissions(&sql_client).await;
+    // let readers = sql_client.who_can_read(Some(&workspace_name), "rac_demo_catalog.rust_schema.dbu_forecasts").await.unwrap();
+    // let readable = sql_client.what_can_principal_read(Some(&workspace_name), "data_analysts").await.unwrap();
+    // let user_grants = sql_client.list_direct_user_grants(Some(&workspace_name)).await.unwrap();
+
+    ////////// Permissions as code
+    // let reconciler: data::reconcile::Reconciler = data::reconcile::Reconciler::new(permissions_client.clone());
+    // let desired: data::reconcile::DesiredState = data::reconcile::DesiredState::from_file("grants.yaml").unwrap();
+    // let plan: data::reconcile::ReconcilePlan = reconciler.plan(&desired).await.unwrap();
+    // plan.print();
+    // if plan.has_drift() { std::process::exit(2); } // fail CI on drift
+    // reconciler.apply(&plan).await.unwrap();
+
     /////////// Data Reading
-    let storage_options: AzureDataLakeGen2Options = permissions_client.authenticate_user(&principal, &db_token, &workspace_name).await.unwrap();
-    let reader: DeltaLakeReader = DeltaLakeReader::new(storage_options, permissions_client.clone(), metastore_client.clone(), String::from(principal));
+    let _active_user: data::permissions::User = permissions_client.authenticate_user(principal, &db_token, &workspace_name).await
+        .map_err(|e| Error::new(ErrorKind::PermissionDenied, e.to_string()))?;
+    let reader: DeltaLakeReader = DeltaLakeReader::new(config.storage.clone(), permissions_client.clone(), metastore_client.clone(), String::from(principal));
 
 
     let table_name: &str = "rac_demo_catalog.rust_schema.dbu_forecasts";
 
-    // let df = reader.read_delta_table_as_datafusion(table_path).await.unwrap();
-
-    // let pdf: polars::prelude::DataFrame = reader.read_delta_table_as_polars(table_name, false).await.unwrap();
-    let pdf: polars::prelude::DataFrame = reader.read_delta_table_as_polars(table_name, true).await.unwrap();
+    // let df = reader.read_delta_table_as_datafusion(table_path, &ReadOptions::new()).await.unwrap();
+    // tables with row filters or column masks are refused unless policies are applied through datafusion
+    // let reader = reader.with_policy_enforcement(data::delta::PolicyEnforcement::Apply);
+    // hide a column from a user or group, the reader projects it out of both read paths
+    // sql_client.add_column_policy(&workspace_name, table_name, "forecast_cost", "external_contractors").await.unwrap();
+    // record every read attempt in the access_audit_log table and a json lines file
+    // let reader = reader.with_audit_logger(data::audit::AuditLogger::new(sql_client.clone()).with_jsonl_file("access_audit.jsonl"));
+    // read storage with table scoped credentials vended by unity catalog
+    // let reader = reader.with_credential_vending();
+
+    // let pdf: polars::prelude::DataFrame = reader.read_delta_table_as_polars(table_name, false, &ReadOptions::new()).await.unwrap();
+    // time travel to a version or to what the table held at a point in time
+    // let pdf: polars::prelude::DataFrame = reader.read_delta_table_as_polars(table_name, true, &ReadOptions::new().with_version(3)).await.unwrap();
+    // let pdf: polars::prelude::DataFrame = reader.read_delta_table_as_polars(table_name, true, &ReadOptions::new().with_timestamp("2024-01-01T00:00:00Z")).await.unwrap();
+    // compose a query plan over the table, files are only downloaded on collect
+    // let lf: polars::prelude::LazyFrame = reader.scan_delta_table(table_name, &ReadOptions::new()).await.unwrap();
+    // let pdf: polars::prelude::DataFrame = lf.select([polars::prelude::col("forecast_cost")]).limit(10).collect().unwrap();
+    // process tables larger than memory file by file, with at most 4 downloads in flight
+    // let mut stream = reader.stream_delta_table_as_polars(table_name, &ReadOptions::new(), 4).await.unwrap();
+    // while let Some(chunk) = futures::StreamExt::next(&mut stream).await { println!("{}", chunk.unwrap().height()); }
+    // only download the needed columns and the files that can hold matching rows
+    // let options: ReadOptions = ReadOptions::new().with_columns(&["forecast_date", "forecast_cost"]).with_filter(data::filter::Filter::eq("forecast_date", "2024-01-01"));
+    // let pdf: polars::prelude::DataFrame = reader.read_delta_table_as_polars(table_name, true, &options).await.unwrap();
+    // rows inserted, updated and deleted since version 5 of a table with delta.enableChangeDataFeed set
+    // let changes: polars::prelude::DataFrame = reader.read_change_feed_as_polars(table_name, &data::delta::ChangeFeedOptions::from_version(5)).await.unwrap();
+    // commit history and size of the table, both are also stored in sqlite for the catalog
+    // let history = reader.table_history(table_name, Some(20)).await.unwrap();
+    // let details = reader.table_details(table_name).await.unwrap();
+    // let stored = sql_client.get_table_details(Some(&workspace_name), table_name).await.unwrap();
+    let pdf: polars::prelude::DataFrame = reader.read_delta_table_as_polars(table_name, true, &ReadOptions::new()).await.unwrap();
     println!("{}", pdf);
+
+    /////////// Data Writing
+    // appends or overwrites a table the principal can modify, columns are matched to the table schema by name
+    // let writer: data::delta::DeltaLakeWriter = data::delta::DeltaLakeWriter::new(config.storage.clone(), permissions_client.clone(), metastore_client.clone(), String::from(principal));
+    // let version: i64 = writer.write_polars(table_name, &mut pdf.clone(), data::delta::WriteMode::Append).await.unwrap();
+    // upsert on the key columns and delete by predicate, both return the rows inserted, updated and deleted
+    // let options = data::delta::MergeOptions::new(&["forecast_date"]).when_matched_update_all().when_not_matched_insert_all();
+    // let metrics = writer.merge_polars(table_name, &mut pdf.clone(), &options).await.unwrap();
+    // let metrics = writer.delete_rows(table_name, "forecast_date < '2023-01-01'").await.unwrap();
     Ok(())
 
 }
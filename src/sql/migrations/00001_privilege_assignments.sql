@@ -0,0 +1,11 @@
+CREATE TABLE IF NOT EXISTS privilege_assignments (
+    securable_type TEXT,
+    full_name TEXT,
+    principal TEXT,
+    principal_type TEXT, -- user, group or service_principal
+    privilege TEXT,
+    updated_at INTEGER,
+    PRIMARY KEY (securable_type, full_name, principal, privilege)
+);
+
+CREATE INDEX IF NOT EXISTS idx_privilege_assignments_principal ON privilege_assignments (principal);
@@ -0,0 +1,15 @@
+CREATE TABLE IF NOT EXISTS column_policies (
+    table_full_name TEXT,
+    column_name TEXT,
+    principal TEXT, -- user or group the column is hidden from
+    created_at INTEGER,
+    PRIMARY KEY (table_full_name, column_name, principal)
+);
+
+CREATE TABLE IF NOT EXISTS column_policy_audit (
+    audit_id INTEGER PRIMARY KEY AUTOINCREMENT,
+    principal TEXT,
+    table_full_name TEXT,
+    hidden_columns TEXT, -- comma separated
+    event_time INTEGER
+);
@@ -0,0 +1,27 @@
+CREATE TABLE IF NOT EXISTS access_audit_log (
+    audit_id INTEGER PRIMARY KEY AUTOINCREMENT,
+    principal TEXT,
+    table_name TEXT,
+    operation TEXT,
+    decision TEXT, -- allowed, denied or error
+    reason TEXT,
+    hidden_columns TEXT,
+    rows_returned INTEGER,
+    bytes_read INTEGER,
+    started_at INTEGER,
+    duration_ms INTEGER
+);
+
+CREATE INDEX IF NOT EXISTS idx_access_audit_log_principal ON access_audit_log (principal, started_at);
+CREATE INDEX IF NOT EXISTS idx_access_audit_log_table ON access_audit_log (table_name, started_at);
+
+-- the audit log is append only
+CREATE TRIGGER IF NOT EXISTS access_audit_log_no_update BEFORE UPDATE ON access_audit_log
+BEGIN
+    SELECT RAISE(ABORT, 'access_audit_log is append only');
+END;
+
+CREATE TRIGGER IF NOT EXISTS access_audit_log_no_delete BEFORE DELETE ON access_audit_log
+BEGIN
+    SELECT RAISE(ABORT, 'access_audit_log is append only');
+END;
@@ -0,0 +1,32 @@
+CREATE TABLE IF NOT EXISTS storage_credentials (
+    id TEXT PRIMARY KEY,
+    name TEXT,
+    owner TEXT,
+    comment TEXT,
+    read_only BOOLEAN,
+    used_for_managed_storage BOOLEAN,
+    metastore_id TEXT,
+    isolation_mode TEXT,
+    created_at INTEGER,
+    created_by TEXT,
+    updated_at INTEGER,
+    updated_by TEXT
+);
+
+
+
+CREATE TABLE IF NOT EXISTS external_locations (
+    name TEXT PRIMARY KEY,
+    url TEXT,
+    credential_name TEXT,
+    credential_id TEXT,
+    owner TEXT,
+    comment TEXT,
+    read_only BOOLEAN,
+    metastore_id TEXT,
+    isolation_mode TEXT,
+    created_at INTEGER,
+    created_by TEXT,
+    updated_at INTEGER,
+    updated_by TEXT
+);
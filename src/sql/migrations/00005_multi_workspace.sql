@@ -0,0 +1,156 @@
+-- key the mirror by workspace and metastore so several workspaces can sync into one database
+-- rows mirrored before this migration keep an empty workspace_name until the next refresh
+CREATE TABLE IF NOT EXISTS workspaces (
+    workspace_name TEXT PRIMARY KEY,
+    metastore_id TEXT,
+    workspace_id INTEGER,
+    default_catalog_name TEXT,
+    last_synced_at INTEGER
+);
+
+
+
+CREATE TABLE catalogs_new (
+    workspace_name TEXT NOT NULL DEFAULT '',
+    name TEXT NOT NULL,
+    owner TEXT ,
+    comment TEXT,
+    storage_root TEXT,
+    provider_name TEXT,
+    share_name TEXT,
+    enable_predictive_optimization TEXT,
+    metastore_id TEXT NOT NULL DEFAULT '',
+    created_at INTEGER ,
+    created_by TEXT ,
+    updated_at INTEGER,
+    updated_by TEXT,
+    catalog_type TEXT,
+    storage_location TEXT,
+    isolation_mode TEXT,
+    connection_name TEXT,
+    full_name TEXT,
+    securable_kind TEXT,
+    securable_type TEXT,
+    browse_only BOOLEAN,
+    PRIMARY KEY (workspace_name, metastore_id, name)
+);
+INSERT INTO catalogs_new (workspace_name, name, owner, comment, storage_root, provider_name, share_name, enable_predictive_optimization, metastore_id, created_at, created_by, updated_at, updated_by, catalog_type, storage_location, isolation_mode, connection_name, full_name, securable_kind, securable_type, browse_only)
+SELECT '', name, owner, comment, storage_root, provider_name, share_name, enable_predictive_optimization, COALESCE(metastore_id, ''), created_at, created_by, updated_at, updated_by, catalog_type, storage_location, isolation_mode, connection_name, full_name, securable_kind, securable_type, browse_only
+FROM catalogs;
+DROP TABLE catalogs;
+ALTER TABLE catalogs_new RENAME TO catalogs;
+
+
+
+CREATE TABLE schemas_new (
+    workspace_name TEXT NOT NULL DEFAULT '',
+    schema_id TEXT NOT NULL,
+    name TEXT,
+    catalog_name TEXT,
+    owner TEXT,
+    comment TEXT,
+    storage_root TEXT,
+    enable_predictive_optimization TEXT,
+    metastore_id TEXT NOT NULL DEFAULT '',
+    full_name TEXT,
+    storage_location TEXT,
+    created_at INTEGER,
+    created_by TEXT,
+    updated_at INTEGER,
+    updated_by TEXT,
+    catalog_type TEXT,
+    browse_only BOOLEAN,
+    PRIMARY KEY (workspace_name, metastore_id, schema_id)
+);
+INSERT INTO schemas_new (workspace_name, schema_id, name, catalog_name, owner, comment, storage_root, enable_predictive_optimization, metastore_id, full_name, storage_location, created_at, created_by, updated_at, updated_by, catalog_type, browse_only)
+SELECT '', schema_id, name, catalog_name, owner, comment, storage_root, enable_predictive_optimization, COALESCE(metastore_id, ''), full_name, storage_location, created_at, created_by, updated_at, updated_by, catalog_type, browse_only
+FROM schemas;
+DROP TABLE schemas;
+ALTER TABLE schemas_new RENAME TO schemas;
+
+
+
+CREATE TABLE tables_new (
+    workspace_name TEXT NOT NULL DEFAULT '',
+    table_id TEXT NOT NULL,
+    name TEXT,
+    catalog_name TEXT,
+    schema_name TEXT,
+    table_type TEXT,
+    data_source_format TEXT,
+    storage_location TEXT,
+    view_definition TEXT,
+    sql_path TEXT,
+    owner TEXT,
+    comment TEXT,
+    storage_credential_name TEXT,
+    enable_predictive_optimization TEXT,
+    metastore_id TEXT NOT NULL DEFAULT '',
+    full_name TEXT,
+    data_access_configuration_id TEXT,
+    created_at INTEGER,
+    created_by TEXT,
+    updated_at INTEGER,
+    updated_by TEXT,
+    deleted_at INTEGER,
+    access_point TEXT,
+    pipeline_id TEXT,
+    browse_only BOOLEAN,
+    PRIMARY KEY (workspace_name, metastore_id, table_id)
+);
+INSERT INTO tables_new (workspace_name, table_id, name, catalog_name, schema_name, table_type, data_source_format, storage_location, view_definition, sql_path, owner, comment, storage_credential_name, enable_predictive_optimization, metastore_id, full_name, data_access_configuration_id, created_at, created_by, updated_at, updated_by, deleted_at, access_point, pipeline_id, browse_only)
+SELECT '', table_id, name, catalog_name, schema_name, table_type, data_source_format, storage_location, view_definition, sql_path, owner, comment, storage_credential_name, enable_predictive_optimization, COALESCE(metastore_id, ''), full_name, data_access_configuration_id, created_at, created_by, updated_at, updated_by, deleted_at, access_point, pipeline_id, browse_only
+FROM tables;
+DROP TABLE tables;
+ALTER TABLE tables_new RENAME TO tables;
+
+CREATE INDEX IF NOT EXISTS idx_tables_full_name ON tables (full_name);
+
+
+
+CREATE TABLE storage_credentials_new (
+    workspace_name TEXT NOT NULL DEFAULT '',
+    id TEXT NOT NULL,
+    name TEXT,
+    owner TEXT,
+    comment TEXT,
+    read_only BOOLEAN,
+    used_for_managed_storage BOOLEAN,
+    metastore_id TEXT NOT NULL DEFAULT '',
+    isolation_mode TEXT,
+    created_at INTEGER,
+    created_by TEXT,
+    updated_at INTEGER,
+    updated_by TEXT,
+    PRIMARY KEY (workspace_name, metastore_id, id)
+);
+INSERT INTO storage_credentials_new (workspace_name, id, name, owner, comment, read_only, used_for_managed_storage, metastore_id, isolation_mode, created_at, created_by, updated_at, updated_by)
+SELECT '', id, name, owner, comment, read_only, used_for_managed_storage, COALESCE(metastore_id, ''), isolation_mode, created_at, created_by, updated_at, updated_by
+FROM storage_credentials;
+DROP TABLE storage_credentials;
+ALTER TABLE storage_credentials_new RENAME TO storage_credentials;
+
+
+
+CREATE TABLE external_locations_new (
+    workspace_name TEXT NOT NULL DEFAULT '',
+    name TEXT NOT NULL,
+    url TEXT,
+    credential_name TEXT,
+    credential_id TEXT,
+    owner TEXT,
+    comment TEXT,
+    read_only BOOLEAN,
+    metastore_id TEXT NOT NULL DEFAULT '',
+    isolation_mode TEXT,
+    created_at INTEGER,
+    created_by TEXT,
+    updated_at INTEGER,
+    updated_by TEXT,
+    PRIMARY KEY (workspace_name, metastore_id, name)
+);
+INSERT INTO external_locations_new (workspace_name, name, url, credential_name, credential_id, owner, comment, read_only, metastore_id, isolation_mode, created_at, created_by, updated_at, updated_by)
+SELECT '', name, url, credential_name, credential_id, owner, comment, read_only, COALESCE(metastore_id, ''), isolation_mode, created_at, created_by, updated_at, updated_by
+FROM external_locations;
+DROP TABLE external_locations;
+ALTER TABLE external_locations_new RENAME TO external_locations;
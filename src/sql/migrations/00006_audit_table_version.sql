@@ -0,0 +1,2 @@
+-- the delta version a read resolved to, so time travel reads can be reproduced
+ALTER TABLE access_audit_log ADD COLUMN table_version INTEGER;
@@ -0,0 +1,35 @@
+-- delta log insights keyed like the tables mirror, so size and freshness are known without opening the table
+CREATE TABLE IF NOT EXISTS table_details (
+    workspace_name TEXT NOT NULL DEFAULT '',
+    metastore_id TEXT NOT NULL DEFAULT '',
+    table_id TEXT NOT NULL,
+    table_name TEXT NOT NULL,
+    table_version INTEGER NOT NULL,
+    last_commit_timestamp INTEGER,
+    num_files INTEGER NOT NULL,
+    size_in_bytes INTEGER NOT NULL,
+    partition_columns TEXT,
+    min_reader_version INTEGER,
+    min_writer_version INTEGER,
+    reader_features TEXT,
+    writer_features TEXT,
+    refreshed_at INTEGER,
+    PRIMARY KEY (workspace_name, metastore_id, table_id)
+);
+CREATE INDEX IF NOT EXISTS idx_table_details_table_name ON table_details (table_name);
+
+
+
+CREATE TABLE IF NOT EXISTS table_history (
+    workspace_name TEXT NOT NULL DEFAULT '',
+    metastore_id TEXT NOT NULL DEFAULT '',
+    table_id TEXT NOT NULL,
+    table_name TEXT NOT NULL,
+    version INTEGER NOT NULL,
+    commit_timestamp INTEGER,
+    operation TEXT,
+    operation_parameters TEXT, -- json object
+    user_name TEXT,
+    PRIMARY KEY (workspace_name, metastore_id, table_id, version)
+);
+CREATE INDEX IF NOT EXISTS idx_table_history_table_name ON table_history (table_name);
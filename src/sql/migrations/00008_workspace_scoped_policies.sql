@@ -0,0 +1,40 @@
+-- key grants and column policies by workspace and metastore like the mirror, so syncing one workspace does not replace another's
+-- rows stored before this migration keep an empty workspace_name until the next refresh
+CREATE TABLE privilege_assignments_new (
+    workspace_name TEXT NOT NULL DEFAULT '',
+    metastore_id TEXT NOT NULL DEFAULT '',
+    securable_type TEXT,
+    full_name TEXT,
+    principal TEXT,
+    principal_type TEXT, -- user, group or service_principal
+    privilege TEXT,
+    updated_at INTEGER,
+    PRIMARY KEY (workspace_name, metastore_id, securable_type, full_name, principal, privilege)
+);
+INSERT INTO privilege_assignments_new (workspace_name, metastore_id, securable_type, full_name, principal, principal_type, privilege, updated_at)
+SELECT '', '', securable_type, full_name, principal, principal_type, privilege, updated_at
+FROM privilege_assignments;
+DROP TABLE privilege_assignments;
+ALTER TABLE privilege_assignments_new RENAME TO privilege_assignments;
+CREATE INDEX IF NOT EXISTS idx_privilege_assignments_principal ON privilege_assignments (principal);
+
+
+
+CREATE TABLE column_policies_new (
+    workspace_name TEXT NOT NULL DEFAULT '',
+    metastore_id TEXT NOT NULL DEFAULT '',
+    table_full_name TEXT,
+    column_name TEXT,
+    principal TEXT, -- user or group the column is hidden from
+    created_at INTEGER,
+    PRIMARY KEY (workspace_name, metastore_id, table_full_name, column_name, principal)
+);
+INSERT INTO column_policies_new (workspace_name, metastore_id, table_full_name, column_name, principal, created_at)
+SELECT '', '', table_full_name, column_name, principal, created_at
+FROM column_policies;
+DROP TABLE column_policies;
+ALTER TABLE column_policies_new RENAME TO column_policies;
+
+
+
+ALTER TABLE column_policy_audit ADD COLUMN workspace_name TEXT NOT NULL DEFAULT '';
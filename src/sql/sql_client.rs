@@ -1,7 +1,10 @@
 // https://github.com/launchbadge/sqlx/tree/main/examples/sqlite/todos
 use log;
 use sqlx::migrate::{MigrateError, MigrateDatabase};
-use crate::data::metastore::{CatalogResponse, SchemaResponse, TableResponse};
+use crate::data::metastore::{CatalogResponse, SchemaResponse, TableResponse, StorageCredentialResponse, ExternalLocationResponse, ExternalLocation, MetastoreAssignment, Table};
+use crate::data::delta::{TableCommit, TableDetails};
+use crate::data::permissions::PrivilegeAssignmentsResponse;
+use crate::data::audit::{AuditRecord, AuditSummary};
 use sqlx::{Error, Sqlite, FromRow};
 use sqlx::sqlite::{SqliteQueryResult, SqlitePool};
 
@@ -65,7 +68,7 @@ impl SqlClient {
         migration_results
     }
 
-    pub async fn write_catalogs(&self, catalog_response: CatalogResponse) -> Result<(), sqlx::Error> {
+    pub async fn write_catalogs(&self, workspace_name: &str, catalog_response: CatalogResponse) -> Result<(), sqlx::Error> {
         // let mut tx = self.pool.begin().await?;
         // let conn = self.pool.acquire().await?;
         // let mut tx = conn.begin().await?;
@@ -73,8 +76,8 @@ impl SqlClient {
         for catalog in catalog_response.catalogs {
             if catalog.catalog_type != "DELTASHARING_CATALOG" && catalog.name != "__databricks_internal" {
                 let _result: SqliteQueryResult = sqlx::query(
-                    "INSERT OR REPLACE INTO catalogs (name, owner, comment, storage_root, provider_name, share_name, enable_predictive_optimization, metastore_id, created_at, created_by, updated_at, updated_by, catalog_type, storage_location, isolation_mode, connection_name, full_name, securable_kind, securable_type, browse_only)
-                    VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16, $17, $18, $19, $20)"
+                    "INSERT OR REPLACE INTO catalogs (name, owner, comment, storage_root, provider_name, share_name, enable_predictive_optimization, metastore_id, created_at, created_by, updated_at, updated_by, catalog_type, storage_location, isolation_mode, connection_name, full_name, securable_kind, securable_type, browse_only, workspace_name)
+                    VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16, $17, $18, $19, $20, $21)"
                 )
                 .bind(&catalog.name)
                 .bind(&catalog.owner)
@@ -96,6 +99,7 @@ impl SqlClient {
                 .bind(&catalog.securable_kind)
                 .bind(&catalog.securable_type)
                 .bind(&catalog.browse_only)
+                .bind(workspace_name)
                 .execute(&self.pool)
                 // .execute(&mut tx)
                 .await?;
@@ -106,13 +110,13 @@ impl SqlClient {
     }
     
 
-    pub async fn write_schemas(&self, schema_response: SchemaResponse) -> Result<(), sqlx::Error> {
+    pub async fn write_schemas(&self, workspace_name: &str, schema_response: SchemaResponse) -> Result<(), sqlx::Error> {
         if let Some(schemas) = schema_response.schemas {
             for schema in schemas {
                 log::info!("Catalog: {} | Schema: {}", schema.catalog_name, schema.name);
                 let _result = sqlx::query(
-                    "INSERT OR REPLACE INTO schemas (name, catalog_name, owner, comment, storage_root, enable_predictive_optimization, metastore_id, full_name, storage_location, created_at, created_by, updated_at, updated_by, catalog_type, browse_only, schema_id) 
-                    VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16)"
+                    "INSERT OR REPLACE INTO schemas (name, catalog_name, owner, comment, storage_root, enable_predictive_optimization, metastore_id, full_name, storage_location, created_at, created_by, updated_at, updated_by, catalog_type, browse_only, schema_id, workspace_name) 
+                    VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16, $17)"
                 )
                 .bind(&schema.name)
                 .bind(&schema.catalog_name)
@@ -130,6 +134,7 @@ impl SqlClient {
                 .bind(&schema.catalog_type)
                 .bind(&schema.browse_only)
                 .bind(&schema.schema_id)
+                .bind(workspace_name)
                 .execute(&self.pool)
                 .await?;
 
@@ -139,14 +144,14 @@ impl SqlClient {
         Ok(())
     }
 
-    pub async fn write_tables(&self, table_response: TableResponse) -> Result<(), sqlx::Error> {
+    pub async fn write_tables(&self, workspace_name: &str, table_response: TableResponse) -> Result<(), sqlx::Error> {
         log::info!("Writing Tables!");
         if let Some(tables) = table_response.tables {
             for table in tables {
                 log::info!(" Catalog: {} | Schema: {} | Table: {}", table.catalog_name, table.schema_name, table.name);
                 let result = sqlx::query(
-                    "INSERT OR REPLACE INTO tables (name, catalog_name, schema_name, table_type, data_source_format, storage_location, view_definition, sql_path, owner, comment, storage_credential_name, enable_predictive_optimization, metastore_id, full_name, data_access_configuration_id, created_at, created_by, updated_at, updated_by, deleted_at, table_id, access_point, pipeline_id, browse_only) 
-                    VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16, $17, $18, $19, $20, $21, $22, $23, $24)"
+                    "INSERT OR REPLACE INTO tables (name, catalog_name, schema_name, table_type, data_source_format, storage_location, view_definition, sql_path, owner, comment, storage_credential_name, enable_predictive_optimization, metastore_id, full_name, data_access_configuration_id, created_at, created_by, updated_at, updated_by, deleted_at, table_id, access_point, pipeline_id, browse_only, workspace_name) 
+                    VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16, $17, $18, $19, $20, $21, $22, $23, $24, $25)"
                 )
                 .bind(&table.name)
                 .bind(&table.catalog_name)
@@ -172,6 +177,7 @@ impl SqlClient {
                 .bind(&table.access_point)
                 .bind(&table.pipeline_id)
                 .bind(&table.browse_only)
+                .bind(workspace_name)
                 .execute(&self.pool)
                 .await;
                 
@@ -189,123 +195,590 @@ impl SqlClient {
         Ok(())
     }
 
-    pub async fn list_catalogs(&self, search_term: Option<&str>) -> Result<Vec<ListCatalogResultSet>, sqlx::Error> {
-        let mut qry: String = String::from("select name from catalogs");
+    pub async fn write_storage_credentials(&self, workspace_name: &str, credential_response: StorageCredentialResponse) -> Result<(), sqlx::Error> {
+        if let Some(credentials) = credential_response.storage_credentials {
+            for credential in credentials {
+                log::info!("Storage Credential: {}", credential.name);
+                sqlx::query(
+                    "INSERT OR REPLACE INTO storage_credentials (id, name, owner, comment, read_only, used_for_managed_storage, metastore_id, isolation_mode, created_at, created_by, updated_at, updated_by, workspace_name)
+                    VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13)"
+                )
+                .bind(&credential.id)
+                .bind(&credential.name)
+                .bind(&credential.owner)
+                .bind(&credential.comment)
+                .bind(credential.read_only)
+                .bind(credential.used_for_managed_storage)
+                .bind(&credential.metastore_id)
+                .bind(&credential.isolation_mode)
+                .bind(credential.created_at)
+                .bind(&credential.created_by)
+                .bind(credential.updated_at)
+                .bind(&credential.updated_by)
+                .bind(workspace_name)
+                .execute(&self.pool)
+                .await?;
+            }
+        }
+        Ok(())
+    }
 
-        if let Some(term) = search_term {
-            qry.push_str(&format!(" WHERE name LIKE '%{}%'", term));
+    pub async fn write_external_locations(&self, workspace_name: &str, location_response: ExternalLocationResponse) -> Result<(), sqlx::Error> {
+        if let Some(locations) = location_response.external_locations {
+            for location in locations {
+                log::info!("External Location: {} | {}", location.name, location.url);
+                sqlx::query(
+                    "INSERT OR REPLACE INTO external_locations (name, url, credential_name, credential_id, owner, comment, read_only, metastore_id, isolation_mode, created_at, created_by, updated_at, updated_by, workspace_name)
+                    VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14)"
+                )
+                .bind(&location.name)
+                .bind(&location.url)
+                .bind(&location.credential_name)
+                .bind(&location.credential_id)
+                .bind(&location.owner)
+                .bind(&location.comment)
+                .bind(location.read_only)
+                .bind(&location.metastore_id)
+                .bind(&location.isolation_mode)
+                .bind(location.created_at)
+                .bind(&location.created_by)
+                .bind(location.updated_at)
+                .bind(&location.updated_by)
+                .bind(workspace_name)
+                .execute(&self.pool)
+                .await?;
+            }
         }
+        Ok(())
+    }
 
+    // all workspaces when workspace_name is None
+    pub async fn list_external_locations(&self, workspace_name: Option<&str>) -> Result<Vec<ExternalLocation>, sqlx::Error> {
+        let results: Vec<ExternalLocation> = sqlx::query_as::<_, ExternalLocation>(
+            "SELECT name, url, credential_name, credential_id, owner, comment, read_only, metastore_id, isolation_mode, created_at, created_by, updated_at, updated_by
+            FROM external_locations
+            WHERE ($1 IS NULL OR workspace_name = $1)"
+        )
+        .bind(workspace_name)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(results)
+    }
 
-        
-        let catalog_results: Vec<ListCatalogResultSet> = sqlx::query_as::<_, ListCatalogResultSet>(&qry)
-            .fetch_all(&self.pool)
-            .await
-            .unwrap();
+    pub async fn list_table_locations(&self, workspace_name: Option<&str>) -> Result<Vec<TableLocationResultSet>, sqlx::Error> {
+        let results: Vec<TableLocationResultSet> = sqlx::query_as::<_, TableLocationResultSet>(
+            "SELECT workspace_name, full_name, storage_location, storage_credential_name FROM tables
+            WHERE ($1 IS NULL OR workspace_name = $1)
+            ORDER BY workspace_name, full_name"
+        )
+        .bind(workspace_name)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(results)
+    }
 
-        for cat in &catalog_results {
-            println!(
-                "Catalog Name: {}", cat.name );
+    pub async fn write_workspace(&self, assignment: &MetastoreAssignment) -> Result<(), sqlx::Error> {
+        let last_synced_at: i64 = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_millis() as i64)
+            .unwrap_or(0);
+
+        sqlx::query(
+            "INSERT OR REPLACE INTO workspaces (workspace_name, metastore_id, workspace_id, default_catalog_name, last_synced_at)
+            VALUES ($1, $2, $3, $4, $5)"
+        )
+        .bind(&assignment.workspace_name)
+        .bind(&assignment.metastore_id)
+        .bind(assignment.workspace_id)
+        .bind(&assignment.default_catalog_name)
+        .bind(last_synced_at)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    pub async fn list_workspaces(&self, metastore_id: Option<&str>) -> Result<Vec<MetastoreAssignment>, sqlx::Error> {
+        let results: Vec<MetastoreAssignment> = sqlx::query_as::<_, MetastoreAssignment>(
+            "SELECT workspace_name, metastore_id, workspace_id, default_catalog_name FROM workspaces
+            WHERE ($1 IS NULL OR metastore_id = $1)
+            ORDER BY workspace_name"
+        )
+        .bind(metastore_id)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(results)
+    }
+
+    // replaces every assignment stored for the securable so revoked grants are removed
+    pub async fn write_privilege_assignments(&self, workspace_name: &str, metastore_id: &str, securable_type: &str, full_name: &str, assignments: PrivilegeAssignmentsResponse) -> Result<(), sqlx::Error> {
+        let updated_at: i64 = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_millis() as i64;
+        let mut tx = self.pool.begin().await?;
+
+        sqlx::query("DELETE FROM privilege_assignments WHERE workspace_name = $1 AND metastore_id = $2 AND securable_type = $3 AND full_name = $4")
+            .bind(workspace_name)
+            .bind(metastore_id)
+            .bind(securable_type)
+            .bind(full_name)
+            .execute(&mut *tx)
+            .await?;
+
+        for assignment in assignments.privilege_assignments.unwrap_or_default() {
+            let principal_type: Option<String> = assignment.principal_type;
+            if let (Some(principal), Some(privileges)) = (assignment.principal, assignment.privileges) {
+                for privilege in privileges {
+                    log::info!("{} {} | {} | {}", securable_type, full_name, principal, privilege);
+                    sqlx::query(
+                        "INSERT OR REPLACE INTO privilege_assignments (workspace_name, metastore_id, securable_type, full_name, principal, principal_type, privilege, updated_at)
+                        VALUES ($1, $2, $3, $4, $5, $6, $7, $8)"
+                    )
+                    .bind(workspace_name)
+                    .bind(metastore_id)
+                    .bind(securable_type)
+                    .bind(full_name)
+                    .bind(&principal)
+                    .bind(&principal_type)
+                    .bind(privilege.to_string())
+                    .bind(updated_at)
+                    .execute(&mut *tx)
+                    .await?;
+                }
+            }
         }
 
+        tx.commit().await?;
+        Ok(())
+    }
 
-        Ok(catalog_results)
+    // principals holding SELECT or ALL_PRIVILEGES on the table or inherited from its schema or catalog, plus owners
+    // they also need USE_CATALOG and USE_SCHEMA, which table owners only have implicitly when they own the catalog
+    // grants only apply to the table of the same workspace and metastore, None spans all workspaces
+    pub async fn who_can_read(&self, workspace_name: Option<&str>, table_full_name: &str) -> Result<Vec<PrivilegeResultSet>, sqlx::Error> {
+        let qry: String = format!("{}
+            SELECT g.securable_type, g.full_name, g.principal, g.principal_type, g.privilege
+            FROM table_grants g
+            JOIN can_use u ON u.workspace_name = g.workspace_name AND u.table_full_name = g.table_full_name AND u.principal = g.principal
+            WHERE g.privilege IN ('SELECT', 'ALL_PRIVILEGES')
+            UNION
+            SELECT 'table', t.full_name, t.owner, 'owner', 'OWNER'
+            FROM tables t
+            LEFT JOIN catalogs c ON c.workspace_name = t.workspace_name AND c.name = t.catalog_name
+            WHERE t.full_name = $1 AND ($2 IS NULL OR t.workspace_name = $2)
+            AND (c.owner = t.owner OR EXISTS (
+                SELECT 1 FROM can_use u WHERE u.workspace_name = t.workspace_name AND u.table_full_name = t.full_name AND u.principal = t.owner
+            ))
+            ORDER BY 3", readable_grants_cte("t.full_name = $1 AND ($2 IS NULL OR t.workspace_name = $2)"));
+
+        let results: Vec<PrivilegeResultSet> = sqlx::query_as::<_, PrivilegeResultSet>(&qry)
+            .bind(table_full_name)
+            .bind(workspace_name)
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(results)
     }
 
-    pub async fn list_schemas(&self, catalog_name: Option<&str>, search_term: Option<&str>) -> Result<Vec<ListSchemaResultSet>, sqlx::Error> {
-        let mut qry: String = String::from("select name, catalog_name from schemas");
+    // tables a principal can read through a direct, schema or catalog level grant, or ownership, with USE_CATALOG and USE_SCHEMA
+    pub async fn what_can_principal_read(&self, workspace_name: Option<&str>, principal: &str) -> Result<Vec<PrivilegeResultSet>, sqlx::Error> {
+        let qry: String = format!("{}
+            SELECT 'table' AS securable_type, g.table_full_name AS full_name, g.principal, g.principal_type, g.privilege
+            FROM table_grants g
+            JOIN can_use u ON u.workspace_name = g.workspace_name AND u.table_full_name = g.table_full_name AND u.principal = g.principal
+            WHERE g.privilege IN ('SELECT', 'ALL_PRIVILEGES')
+            UNION
+            SELECT 'table', t.full_name, t.owner, 'owner', 'OWNER'
+            FROM tables t
+            LEFT JOIN catalogs c ON c.workspace_name = t.workspace_name AND c.name = t.catalog_name
+            WHERE t.owner = $1 AND ($2 IS NULL OR t.workspace_name = $2)
+            AND (c.owner = t.owner OR EXISTS (
+                SELECT 1 FROM can_use u WHERE u.workspace_name = t.workspace_name AND u.table_full_name = t.full_name AND u.principal = t.owner
+            ))
+            ORDER BY 2", readable_grants_cte("p.principal = $1 AND ($2 IS NULL OR t.workspace_name = $2)"));
+
+        let results: Vec<PrivilegeResultSet> = sqlx::query_as::<_, PrivilegeResultSet>(&qry)
+            .bind(principal)
+            .bind(workspace_name)
+            .fetch_all(&self.pool)
+            .await?;
 
-        if catalog_name.is_some() || search_term.is_some() {
-            qry.push_str(" WHERE ");
-        }    
-        
-        if let Some(term) = search_term {
-            qry.push_str(&format!("  name LIKE '%{}%'", term));
-        }
+        Ok(results)
+    }
+
+    // grants made to individual users instead of groups
+    pub async fn list_direct_user_grants(&self, workspace_name: Option<&str>) -> Result<Vec<PrivilegeResultSet>, sqlx::Error> {
+        let results: Vec<PrivilegeResultSet> = sqlx::query_as::<_, PrivilegeResultSet>(
+            "SELECT securable_type, full_name, principal, principal_type, privilege
+            FROM privilege_assignments
+            WHERE principal_type = 'user'
+            AND ($1 IS NULL OR workspace_name = $1)
+            ORDER BY full_name, principal"
+        )
+        .bind(workspace_name)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(results)
+    }
+
+    // hides a column of a table from a user or group, keyed by the metastore of the table's row in the mirror
+    pub async fn add_column_policy(&self, workspace_name: &str, table_full_name: &str, column_name: &str, principal: &str) -> Result<(), sqlx::Error> {
+        let created_at: i64 = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_millis() as i64;
+        sqlx::query(
+            "INSERT OR REPLACE INTO column_policies (workspace_name, metastore_id, table_full_name, column_name, principal, created_at)
+            VALUES ($1, COALESCE((SELECT metastore_id FROM tables WHERE workspace_name = $1 AND full_name = $2), ''), $2, $3, $4, $5)"
+        )
+        .bind(workspace_name)
+        .bind(table_full_name)
+        .bind(column_name)
+        .bind(principal)
+        .bind(created_at)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    pub async fn remove_column_policy(&self, workspace_name: &str, table_full_name: &str, column_name: &str, principal: &str) -> Result<(), sqlx::Error> {
+        sqlx::query("DELETE FROM column_policies WHERE workspace_name = $1 AND table_full_name = $2 AND column_name = $3 AND principal = $4")
+            .bind(workspace_name)
+            .bind(table_full_name)
+            .bind(column_name)
+            .bind(principal)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    // policies stored before they were keyed by workspace have an empty workspace_name and apply to every workspace
+    pub async fn list_column_policies(&self, workspace_name: &str, table_full_name: &str) -> Result<Vec<ColumnPolicyResultSet>, sqlx::Error> {
+        let results: Vec<ColumnPolicyResultSet> = sqlx::query_as::<_, ColumnPolicyResultSet>(
+            "SELECT table_full_name, column_name, principal FROM column_policies
+            WHERE workspace_name IN ($1, '') AND table_full_name = $2
+            ORDER BY column_name, principal"
+        )
+        .bind(workspace_name)
+        .bind(table_full_name)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(results)
+    }
 
-        if let Some(catalog) = catalog_name {
-            if search_term.is_some() {
-                qry.push_str(&format!(" and catalog_name = '{}'", catalog));
-            } else {
-                qry.push_str(&format!("  catalog_name = '{}'", catalog));
+    // columns of a table hidden from any of the given principals i.e. a user and the groups they belong to
+    pub async fn hidden_columns(&self, workspace_name: &str, table_full_name: &str, principals: &[String]) -> Result<Vec<String>, sqlx::Error> {
+        let mut hidden: Vec<String> = Vec::new();
+        for policy in self.list_column_policies(workspace_name, table_full_name).await? {
+            if principals.contains(&policy.principal) && !hidden.contains(&policy.column_name) {
+                hidden.push(policy.column_name);
             }
         }
+        Ok(hidden)
+    }
 
+    pub async fn write_column_policy_audit(&self, workspace_name: &str, principal: &str, table_full_name: &str, hidden_columns: &[String]) -> Result<(), sqlx::Error> {
+        let event_time: i64 = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_millis() as i64;
+        sqlx::query(
+            "INSERT INTO column_policy_audit (workspace_name, principal, table_full_name, hidden_columns, event_time)
+            VALUES ($1, $2, $3, $4, $5)"
+        )
+        .bind(workspace_name)
+        .bind(principal)
+        .bind(table_full_name)
+        .bind(hidden_columns.join(","))
+        .bind(event_time)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
 
-        
-        let schema_results: Vec<ListSchemaResultSet> = sqlx::query_as::<_, ListSchemaResultSet>(&qry)
-            .fetch_all(&self.pool)
-            .await
-            .unwrap();
+    pub async fn write_access_audit(&self, record: &AuditRecord) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "INSERT INTO access_audit_log (principal, table_name, operation, decision, reason, hidden_columns, rows_returned, bytes_read, started_at, duration_ms, table_version)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)"
+        )
+        .bind(&record.principal)
+        .bind(&record.table_name)
+        .bind(&record.operation)
+        .bind(&record.decision)
+        .bind(&record.reason)
+        .bind(&record.hidden_columns)
+        .bind(record.rows_returned)
+        .bind(record.bytes_read)
+        .bind(record.started_at)
+        .bind(record.duration_ms)
+        .bind(record.table_version)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
 
-        for sc in &schema_results {
-            println!(
-                "Catalog Name: {} | Schema Name: {}", sc.catalog_name, sc.name );
+    // audit records filtered by principal, table and a started_at window, newest first
+    pub async fn list_access_audit(&self, principal: Option<&str>, table_name: Option<&str>, since: Option<i64>, until: Option<i64>) -> Result<Vec<AuditRecord>, sqlx::Error> {
+        let results: Vec<AuditRecord> = sqlx::query_as::<_, AuditRecord>(
+            "SELECT principal, table_name, operation, decision, reason, hidden_columns, rows_returned, bytes_read, table_version, started_at, duration_ms
+            FROM access_audit_log
+            WHERE ($1 IS NULL OR principal = $1)
+            AND ($2 IS NULL OR table_name = $2)
+            AND ($3 IS NULL OR started_at >= $3)
+            AND ($4 IS NULL OR started_at < $4)
+            ORDER BY started_at DESC"
+        )
+        .bind(principal)
+        .bind(table_name)
+        .bind(since)
+        .bind(until)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(results)
+    }
+
+    // details are stored next to the table's row in the tables mirror
+    pub async fn write_table_details(&self, workspace_name: &str, table: &Table, details: &TableDetails) -> Result<(), sqlx::Error> {
+        let refreshed_at: i64 = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_millis() as i64)
+            .unwrap_or(0);
+
+        sqlx::query(
+            "INSERT OR REPLACE INTO table_details (workspace_name, metastore_id, table_id, table_name, table_version, last_commit_timestamp, num_files, size_in_bytes,
+            partition_columns, min_reader_version, min_writer_version, reader_features, writer_features, refreshed_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14)"
+        )
+        .bind(workspace_name)
+        .bind(table.metastore_id.clone().unwrap_or_default())
+        .bind(&table.table_id)
+        .bind(&details.table_name)
+        .bind(details.table_version)
+        .bind(details.last_commit_timestamp)
+        .bind(details.num_files)
+        .bind(details.size_in_bytes)
+        .bind(&details.partition_columns)
+        .bind(details.min_reader_version)
+        .bind(details.min_writer_version)
+        .bind(&details.reader_features)
+        .bind(&details.writer_features)
+        .bind(refreshed_at)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    // the latest details of a table by full name, None when they were never refreshed
+    pub async fn get_table_details(&self, workspace_name: Option<&str>, table_name: &str) -> Result<Option<TableDetails>, sqlx::Error> {
+        let result: Option<TableDetails> = sqlx::query_as::<_, TableDetails>(
+            "SELECT table_name, table_version, last_commit_timestamp, num_files, size_in_bytes, partition_columns,
+            min_reader_version, min_writer_version, reader_features, writer_features
+            FROM table_details
+            WHERE ($1 IS NULL OR workspace_name = $1)
+            AND table_name = $2
+            ORDER BY refreshed_at DESC
+            LIMIT 1"
+        )
+        .bind(workspace_name)
+        .bind(table_name)
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(result)
+    }
+
+    // commits are immutable, so rewriting a version that is already stored only replaces it with the same values
+    pub async fn write_table_history(&self, workspace_name: &str, table: &Table, history: &[TableCommit]) -> Result<(), sqlx::Error> {
+        let metastore_id: String = table.metastore_id.clone().unwrap_or_default();
+        let mut tx = self.pool.begin().await?;
+        for commit in history {
+            sqlx::query(
+                "INSERT OR REPLACE INTO table_history (workspace_name, metastore_id, table_id, table_name, version, commit_timestamp, operation, operation_parameters, user_name)
+                VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)"
+            )
+            .bind(workspace_name)
+            .bind(&metastore_id)
+            .bind(&table.table_id)
+            .bind(&table.full_name)
+            .bind(commit.version)
+            .bind(commit.commit_timestamp)
+            .bind(&commit.operation)
+            .bind(&commit.operation_parameters)
+            .bind(&commit.user_name)
+            .execute(&mut *tx)
+            .await?;
         }
+        tx.commit().await?;
+        Ok(())
+    }
 
+    // stored commits of a table by full name, newest first
+    pub async fn list_table_history(&self, workspace_name: Option<&str>, table_name: &str, limit: Option<i64>) -> Result<Vec<TableCommit>, sqlx::Error> {
+        let results: Vec<TableCommit> = sqlx::query_as::<_, TableCommit>(
+            "SELECT version, commit_timestamp, operation, operation_parameters, user_name
+            FROM table_history
+            WHERE ($1 IS NULL OR workspace_name = $1)
+            AND table_name = $2
+            ORDER BY version DESC
+            LIMIT COALESCE($3, -1)"
+        )
+        .bind(workspace_name)
+        .bind(table_name)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(results)
+    }
 
-        Ok(schema_results)
+    pub async fn access_audit_summary(&self, since: Option<i64>, until: Option<i64>) -> Result<Vec<AuditSummary>, sqlx::Error> {
+        let results: Vec<AuditSummary> = sqlx::query_as::<_, AuditSummary>(
+            "SELECT principal, table_name, decision, COUNT(*) AS attempts, SUM(rows_returned) AS rows_returned, SUM(bytes_read) AS bytes_read,
+            MIN(started_at) AS first_attempt, MAX(started_at) AS last_attempt
+            FROM access_audit_log
+            WHERE ($1 IS NULL OR started_at >= $1)
+            AND ($2 IS NULL OR started_at < $2)
+            GROUP BY principal, table_name, decision
+            ORDER BY principal, table_name, decision"
+        )
+        .bind(since)
+        .bind(until)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(results)
     }
 
-    pub async fn list_tables(&self, catalog_name: Option<&str>, schema_name: Option<&str>, search_term: Option<&str>) -> Result<Vec<ListTableResultSet>, sqlx::Error> {
-        let mut qry: String = String::from("select name, catalog_name, schema_name from tables");
+    pub async fn list_catalogs(&self, workspace_name: Option<&str>, search_term: Option<&str>) -> Result<Vec<ListCatalogResultSet>, sqlx::Error> {
+        let catalog_results: Vec<ListCatalogResultSet> = sqlx::query_as::<_, ListCatalogResultSet>(
+            "SELECT workspace_name, metastore_id, name FROM catalogs
+            WHERE ($1 IS NULL OR workspace_name = $1)
+            AND ($2 IS NULL OR name LIKE '%' || $2 || '%')"
+        )
+        .bind(workspace_name)
+        .bind(search_term)
+        .fetch_all(&self.pool)
+        .await?;
 
-        if catalog_name.is_some() || search_term.is_some() || schema_name.is_some() {
-            qry.push_str(" WHERE ");
-        }    
-        
-        if let Some(term) = search_term {
-            qry.push_str(&format!(" name LIKE '%{}%'", term));
+        for cat in &catalog_results {
+            log::info!("Workspace: {} | Catalog Name: {}", cat.workspace_name, cat.name);
         }
 
-        if let Some(catalog) = catalog_name {
-            if search_term.is_some() {
-                qry.push_str(&format!(" and catalog_name = '{}'", catalog));
-            } else {
-                qry.push_str(&format!("  catalog_name = '{}'", catalog));
-            }
-        }
+        Ok(catalog_results)
+    }
 
-        if let Some(schema) = schema_name {
-            if search_term.is_some() || catalog_name.is_some() {
-                qry.push_str(&format!(" and schema_name = '{}'", schema));
-            } else {
-                qry.push_str(&format!("  schema_name = '{}'", schema));
-            }
+    pub async fn list_schemas(&self, workspace_name: Option<&str>, catalog_name: Option<&str>, search_term: Option<&str>) -> Result<Vec<ListSchemaResultSet>, sqlx::Error> {
+        let schema_results: Vec<ListSchemaResultSet> = sqlx::query_as::<_, ListSchemaResultSet>(
+            "SELECT workspace_name, metastore_id, name, catalog_name FROM schemas
+            WHERE ($1 IS NULL OR workspace_name = $1)
+            AND ($2 IS NULL OR name LIKE '%' || $2 || '%')
+            AND ($3 IS NULL OR catalog_name = $3)"
+        )
+        .bind(workspace_name)
+        .bind(search_term)
+        .bind(catalog_name)
+        .fetch_all(&self.pool)
+        .await?;
+
+        for sc in &schema_results {
+            log::info!("Workspace: {} | Catalog Name: {} | Schema Name: {}", sc.workspace_name, sc.catalog_name, sc.name);
         }
 
+        Ok(schema_results)
+    }
 
-        
-        let table_results: Vec<ListTableResultSet> = sqlx::query_as::<_, ListTableResultSet>(&qry)
-            .fetch_all(&self.pool)
-            .await
-            .unwrap();
+    pub async fn list_tables(&self, workspace_name: Option<&str>, catalog_name: Option<&str>, schema_name: Option<&str>, search_term: Option<&str>) -> Result<Vec<ListTableResultSet>, sqlx::Error> {
+        let table_results: Vec<ListTableResultSet> = sqlx::query_as::<_, ListTableResultSet>(
+            "SELECT workspace_name, metastore_id, name, catalog_name, schema_name FROM tables
+            WHERE ($1 IS NULL OR workspace_name = $1)
+            AND ($2 IS NULL OR name LIKE '%' || $2 || '%')
+            AND ($3 IS NULL OR catalog_name = $3)
+            AND ($4 IS NULL OR schema_name = $4)"
+        )
+        .bind(workspace_name)
+        .bind(search_term)
+        .bind(catalog_name)
+        .bind(schema_name)
+        .fetch_all(&self.pool)
+        .await?;
 
         for t in &table_results {
-            println!(
-                "Catalog Name: {} | Schema Name: {} | Table Name: {}", t.catalog_name, t.schema_name, t.name );
+            log::info!("Workspace: {} | Catalog Name: {} | Schema Name: {} | Table Name: {}", t.workspace_name, t.catalog_name, t.schema_name, t.name);
         }
 
-
         Ok(table_results)
     }
+}
 
+// the grants on each table, its schema and its catalog matching the filter, as table_grants
+// and the principals of each table holding USE_CATALOG and USE_SCHEMA, directly or through ALL_PRIVILEGES, as can_use
+// the filter is a fixed condition on tables t and privilege_assignments p, values are always bound
+fn readable_grants_cte(filter: &str) -> String {
+    format!("
+        WITH table_grants AS (
+            SELECT t.workspace_name, t.full_name AS table_full_name, p.securable_type, p.full_name, p.principal, p.principal_type, p.privilege
+            FROM tables t
+            JOIN privilege_assignments p
+            ON p.workspace_name = t.workspace_name
+            AND p.metastore_id = t.metastore_id
+            AND (
+                (p.securable_type = 'table' AND p.full_name = t.full_name)
+                OR (p.securable_type = 'schema' AND p.full_name = t.catalog_name || '.' || t.schema_name)
+                OR (p.securable_type = 'catalog' AND p.full_name = t.catalog_name)
+            )
+            WHERE {}
+        ),
+        can_use AS (
+            SELECT workspace_name, table_full_name, principal
+            FROM table_grants
+            GROUP BY workspace_name, table_full_name, principal
+            HAVING MAX(securable_type = 'catalog' AND privilege IN ('USE_CATALOG', 'ALL_PRIVILEGES')) = 1
+            AND MAX(securable_type IN ('schema', 'catalog') AND privilege IN ('USE_SCHEMA', 'ALL_PRIVILEGES')) = 1
+        )", filter)
 }
 
 #[derive(Clone, FromRow, Debug)]
 pub struct ListCatalogResultSet {
-    name: String,
+    pub workspace_name: String,
+    pub metastore_id: String,
+    pub name: String,
 }
 
 #[derive(Clone, FromRow, Debug)]
 pub struct ListSchemaResultSet {
-    name: String,
-    catalog_name: String,
+    pub workspace_name: String,
+    pub metastore_id: String,
+    pub name: String,
+    pub catalog_name: String,
 }
 
 #[derive(Clone, FromRow, Debug)]
 pub struct ListTableResultSet {
-    name: String,
-    catalog_name: String,
-    schema_name: String,
+    pub workspace_name: String,
+    pub metastore_id: String,
+    pub name: String,
+    pub catalog_name: String,
+    pub schema_name: String,
+}
+
+#[derive(Clone, FromRow, Debug)]
+pub struct TableLocationResultSet {
+    pub workspace_name: String,
+    pub full_name: String,
+    pub storage_location: Option<String>,
+    pub storage_credential_name: Option<String>,
+}
+
+#[derive(Clone, FromRow, Debug)]
+pub struct ColumnPolicyResultSet {
+    pub table_full_name: String,
+    pub column_name: String,
+    pub principal: String,
+}
+
+#[derive(Clone, FromRow, Debug)]
+pub struct PrivilegeResultSet {
+    pub securable_type: String,
+    pub full_name: String,
+    pub principal: String,
+    pub principal_type: Option<String>, // None when the principal was not found through scim
+    pub privilege: String,
 }
\ No newline at end of file